@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use crate::handler::public_rooms;
+use backend::{data::AppDataSync, db::DatabaseError, models::responses::ApiError};
+use warp::{
+    Filter, Rejection,
+    filters::BoxedFilter,
+    http::StatusCode,
+    reply::{Reply, Response},
+};
+
+const DEFAULT_LEADERBOARD_LIMIT: u32 = 50;
+const MAX_LEADERBOARD_LIMIT: u32 = 500;
+const PROFILE_EMOTE_LIMIT: u32 = 5;
+
+fn error_reply(status: StatusCode, error: String) -> Response {
+    warp::reply::with_status(warp::reply::json(&ApiError { error }), status).into_response()
+}
+
+fn database_error_reply(e: DatabaseError) -> Response {
+    tracing::error!("Database error while serving API request: {e}");
+    error_reply(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Database error".to_string(),
+    )
+}
+
+/// `?emote_set=` narrows the leaderboard down to games played with that set
+/// (as formatted by `emote_set_key`), `?limit=` caps the number of entries
+async fn handle_leaderboard(
+    app_data: AppDataSync,
+    query: HashMap<String, String>,
+) -> Result<Response, Rejection> {
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_LEADERBOARD_LIMIT)
+        .min(MAX_LEADERBOARD_LIMIT);
+
+    Ok(
+        match app_data
+            .db
+            .leaderboard(query.get("emote_set").cloned(), limit)
+            .await
+        {
+            Ok(entries) => warp::reply::json(&entries).into_response(),
+            Err(e) => database_error_reply(e),
+        },
+    )
+}
+
+async fn handle_player_profile(
+    app_data: AppDataSync,
+    login: String,
+) -> Result<Response, Rejection> {
+    Ok(
+        match app_data
+            .db
+            .player_profile(login.to_lowercase(), PROFILE_EMOTE_LIMIT)
+            .await
+        {
+            Ok(Some(profile)) => warp::reply::json(&profile).into_response(),
+            Ok(None) => error_reply(StatusCode::NOT_FOUND, format!("No games played by {login}")),
+            Err(e) => database_error_reply(e),
+        },
+    )
+}
+
+/// Public rooms only, like the room browser
+async fn handle_list_rooms(app_data: AppDataSync) -> Result<Response, Rejection> {
+    Ok(warp::reply::json(&public_rooms(&app_data).await).into_response())
+}
+
+/// Read-only JSON endpoints under `/api`, for things that would rather not
+/// hold a websocket open (overlays, bots)
+pub fn routes(app_data: AppDataSync) -> BoxedFilter<(Response,)> {
+    let with_state = warp::any().map(move || app_data.clone());
+
+    let leaderboard = warp::path!("api" / "leaderboard")
+        .and(warp::get())
+        .and(with_state.clone())
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(handle_leaderboard);
+
+    let player_profile = warp::path!("api" / "players" / String)
+        .and(warp::get())
+        .and(with_state.clone())
+        .and_then(|login, app_data| handle_player_profile(app_data, login));
+
+    let rooms = warp::path!("api" / "rooms")
+        .and(warp::get())
+        .and(with_state)
+        .and_then(handle_list_rooms);
+
+    leaderboard
+        .or(player_profile)
+        .unify()
+        .or(rooms)
+        .unify()
+        .boxed()
+}
@@ -0,0 +1,131 @@
+use serde::Deserialize;
+
+use crate::emotes::{EmoteError, EmoteProvider, FinalEmote, provider_client};
+
+const BTTV_API_HOST: &str = "https://api.betterttv.net";
+const BTTV_CDN_HOST: &str = "https://cdn.betterttv.net";
+
+#[derive(Deserialize, Debug)]
+struct BTTVEmote {
+    id: String,
+    code: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BTTVUserResponse {
+    #[serde(default)]
+    channel_emotes: Vec<BTTVEmote>,
+    #[serde(default)]
+    shared_emotes: Vec<BTTVEmote>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BTTVProvider {
+    client: reqwest::Client,
+    api_host: String,
+    cdn_host: String,
+}
+
+impl Default for BTTVProvider {
+    fn default() -> Self {
+        Self::new(BTTV_API_HOST.to_string(), BTTV_CDN_HOST.to_string())
+    }
+}
+
+impl BTTVProvider {
+    pub fn new(api_host: String, cdn_host: String) -> Self {
+        BTTVProvider {
+            client: provider_client(),
+            api_host,
+            cdn_host,
+        }
+    }
+}
+
+impl EmoteProvider for BTTVProvider {
+    /// `twitch_user_id` is the Twitch ID of the channel whose emotes to use
+    async fn get_emotes(&self, twitch_user_id: &str) -> Result<Vec<FinalEmote>, EmoteError> {
+        let response: BTTVUserResponse = self
+            .client
+            .get(format!(
+                "{}/3/cached/users/twitch/{}",
+                self.api_host, twitch_user_id
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response
+            .channel_emotes
+            .into_iter()
+            .chain(response.shared_emotes)
+            .map(|emote| FinalEmote {
+                url: format!("{}/emote/{}/3x", self.cdn_host, emote.id),
+                name: emote.code,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::{Filter, http::StatusCode};
+
+    use super::*;
+
+    async fn mock_provider() -> BTTVProvider {
+        let users = warp::path!("3" / "cached" / "users" / "twitch" / String).map(|id: String| {
+            let (body, status) = match id.as_str() {
+                "71092938" => (include_str!("../fixtures/bttv_user.json"), StatusCode::OK),
+                _ => (r#"{"message":"user not found"}"#, StatusCode::NOT_FOUND),
+            };
+            warp::reply::with_status(
+                warp::reply::with_header(body, "content-type", "application/json"),
+                status,
+            )
+        });
+        let (addr, server) = warp::serve(users).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        BTTVProvider::new(format!("http://{addr}"), "https://cdn.example".to_string())
+    }
+
+    #[tokio::test]
+    async fn parses_channel_and_shared_emotes() {
+        let emotes = mock_provider().await.get_emotes("71092938").await.unwrap();
+
+        let emotes: Vec<_> = emotes
+            .iter()
+            .map(|e| (e.name.as_str(), e.url.as_str()))
+            .collect();
+        assert_eq!(
+            emotes,
+            [
+                (
+                    "catJAM",
+                    "https://cdn.example/emote/5f1b0186cf6d2144653d2970/3x"
+                ),
+                (
+                    "SourPls",
+                    "https://cdn.example/emote/566ca38765dbbdab32ec0560/3x"
+                ),
+                (
+                    "pepeLaugh",
+                    "https://cdn.example/emote/5e76d338d6581c3724c0f0b2/3x"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_user_is_an_error() {
+        let result = mock_provider().await.get_emotes("1").await;
+        assert!(matches!(
+            result,
+            Err(EmoteError::ReqwestError(e)) if e.status().is_some_and(|s| s.as_u16() == 404)
+        ));
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A single cache entry. The async lock is what makes concurrent lookups of
+/// the same key wait for one fetch instead of each going to the network.
+struct Slot<V> {
+    last_used: Mutex<Instant>,
+    value: tokio::sync::Mutex<Option<(Instant, Arc<V>)>>,
+}
+
+impl<V> Slot<V> {
+    fn new() -> Self {
+        Slot {
+            last_used: Mutex::new(Instant::now()),
+            value: Default::default(),
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    fn last_used(&self) -> Instant {
+        *self.last_used.lock().unwrap()
+    }
+}
+
+/// Keyed cache with a TTL and a bound on the number of keys; the least
+/// recently used key is evicted once the bound is exceeded.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    slots: Mutex<HashMap<K, Arc<Slot<V>>>>,
+}
+
+impl<K: Eq + Hash + Clone, V> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        TtlCache {
+            ttl,
+            capacity,
+            slots: Default::default(),
+        }
+    }
+
+    fn slot(&self, key: &K) -> Arc<Slot<V>> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Slot::new()))
+            .clone();
+        slot.touch();
+
+        while slots.len() > self.capacity {
+            let oldest = slots
+                .iter()
+                .filter(|(k, _)| *k != key)
+                .min_by_key(|(_, slot)| slot.last_used())
+                .map(|(k, _)| k.clone());
+
+            match oldest {
+                Some(k) => slots.remove(&k),
+                None => break,
+            };
+        }
+
+        slot
+    }
+
+    /// Returns the cached value for `key`, calling `fetch` if it is missing or
+    /// expired. Errors are not cached.
+    pub async fn get_or_fetch<F, Fut, E>(&self, key: &K, fetch: F) -> Result<Arc<V>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let slot = self.slot(key);
+        let mut value = slot.value.lock().await;
+
+        if let Some((fetched_at, cached)) = &*value {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(cached.clone());
+            }
+        }
+
+        let fetched = Arc::new(fetch().await?);
+        *value = Some((Instant::now(), fetched.clone()));
+        Ok(fetched)
+    }
+
+    /// Drops `key` from the cache; the next lookup fetches it again
+    pub fn invalidate(&self, key: &K) {
+        self.slots.lock().unwrap().remove(key);
+    }
+
+    /// Fetches `key` again regardless of whether the cached value expired
+    pub async fn refresh<F, Fut, E>(&self, key: &K, fetch: F) -> Result<Arc<V>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        self.invalidate(key);
+        self.get_or_fetch(key, fetch).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use super::*;
+
+    /// A fetch that counts how often it ran and returns that count
+    async fn counted(calls: &AtomicU32) -> Result<u32, Infallible> {
+        Ok(calls.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    #[tokio::test]
+    async fn keeps_values_until_they_expire() {
+        let cache = TtlCache::new(Duration::from_millis(50), 10);
+        let calls = AtomicU32::new(0);
+
+        assert_eq!(*cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap(), 1);
+        assert_eq!(*cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap(), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(*cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_cache_errors() {
+        let cache = TtlCache::new(Duration::from_secs(60), 10);
+
+        let failed: Result<_, &str> = cache.get_or_fetch(&"a", || async { Err("down") }).await;
+        assert!(failed.is_err());
+
+        let fetched: Result<_, &str> = cache.get_or_fetch(&"a", || async { Ok(1) }).await;
+        assert_eq!(*fetched.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn invalidate_and_refresh_fetch_again() {
+        let cache = TtlCache::new(Duration::from_secs(60), 10);
+        let calls = AtomicU32::new(0);
+
+        cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap();
+        cache.invalidate(&"a");
+        assert_eq!(*cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap(), 2);
+
+        assert_eq!(*cache.refresh(&"a", || counted(&calls)).await.unwrap(), 3);
+        assert_eq!(*cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_key() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+        let calls = AtomicU32::new(0);
+
+        cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        cache.get_or_fetch(&"b", || counted(&calls)).await.unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        // "a" is now more recent than "b"
+        cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        cache.get_or_fetch(&"c", || counted(&calls)).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(*cache.get_or_fetch(&"a", || counted(&calls)).await.unwrap(), 1);
+        assert_eq!(*cache.get_or_fetch(&"b", || counted(&calls)).await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn fetches_once_for_concurrent_lookups() {
+        let cache = Arc::new(TtlCache::new(Duration::from_secs(60), 10));
+        let calls = Arc::new(AtomicU32::new(0));
+
+        let lookups = (0..10).map(|_| {
+            let (cache, calls) = (cache.clone(), calls.clone());
+            tokio::spawn(async move {
+                cache
+                    .get_or_fetch(&"a", || async {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        counted(&calls).await
+                    })
+                    .await
+                    .map(|value| *value)
+            })
+        });
+
+        for lookup in lookups.collect::<Vec<_>>() {
+            assert_eq!(lookup.await.unwrap().unwrap(), 1);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,8 +1,16 @@
 use futures_util::stream::SplitSink;
 use jwt_simple::prelude::{HS256Key, HS512Key};
-use std::{collections::HashMap, sync::Arc};
+use rand::{Rng, seq::SliceRandom};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
 use tokio::{
-    sync::{Mutex, RwLock},
+    sync::{Mutex, RwLock, Semaphore},
     task::JoinHandle,
     time::Duration,
 };
@@ -10,12 +18,35 @@ use tokio::{
 use serde::{Deserialize, Serialize};
 use warp::filters::ws::{Message, WebSocket, Ws};
 
-use crate::jwt::{JWTClaim, JWTManager};
+use crate::{
+    db::Database,
+    emotes::{EmoteProviders, EmoteSource},
+    jwt::{JWTClaim, JWTManager},
+    models::responses::GameOverData,
+    scoring::{LateJoinRules, RaceRules, RoundLimit, ScoringRules},
+};
+
+/// Rounds of results a room keeps around after restarting
+const MAX_PAST_ROUNDS: usize = 10;
 
+/// Argon2 is slow on purpose, so a flood of join attempts with passwords
+/// could otherwise take up every core
+const MAX_PASSWORD_CHECKS: usize = 4;
+
+/// Short code players type in to join, see [`crate::room_code`]. Codes are
+/// case-insensitive, so they are kept in upper case.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[serde(from = "String")]
 #[repr(transparent)]
 pub struct RoomID(pub String);
 
+impl From<String> for RoomID {
+    fn from(value: String) -> Self {
+        RoomID(value.trim().to_uppercase())
+    }
+}
+
+/// A player, by Twitch user ID, so reconnecting gets you back the same one
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
 #[repr(transparent)]
 pub struct User(pub String);
@@ -24,11 +55,114 @@ pub struct User(pub String);
 pub struct GameState {
     pub room_id: RoomID,
     pub room_owner: User,
+    pub visibility: RoomVisibility,
+    pub mode: GameMode,
+    /// see [`crate::password`]
+    pub password_hash: Option<String>,
+    /// lowercase Twitch logins allowed to join; anyone can when `None`
+    pub allowlist: Option<HashSet<String>>,
+    /// kept out for as long as the room exists
+    pub banned: HashSet<User>,
+    /// lowercase logins of players and chatters whose guesses reach nobody
+    /// else: no team hints, and chat ignores them
+    pub muted: HashSet<String>,
+    pub max_players: Option<u32>,
+    /// takes over first if the owner leaves
+    pub co_host: Option<User>,
     pub user_data: HashMap<User, UserGameData>,
-    pub emote_set_id: String,
+    /// players in the order they joined, owner included
+    pub join_order: Vec<User>,
+    /// team names; the room doesn't play in teams when there are none
+    pub teams: Vec<String>,
+    /// every player's team, as an index into `teams`
+    pub team_of: HashMap<User, usize>,
+    /// whether guesses that reveal part of an emote are passed on to the
+    /// guesser's teammates
+    pub team_hints: bool,
+    /// watching, not playing; they never show up in scores
+    pub spectators: HashMap<User, Spectator>,
+    pub emote_sources: Vec<EmoteSource>,
+    /// only used with [`RoundLimit::Timer`]
     pub duration: Duration,
+    pub round_limit: RoundLimit,
     pub seed: u64,
+    pub scoring: ScoringRules,
+    pub late_join: LateJoinRules,
+    pub race_rules: RaceRules,
+    pub race: RaceRound,
+    /// whether the owner's Twitch chat plays along as a team; always on in
+    /// [`GameMode::StreamerVsChat`]
+    pub twitch_chat: bool,
+    pub chat: ChatTeam,
     pub timer_handle: Option<JoinHandle<()>>,
+    pub started_at: Option<SystemTime>,
+    /// new for every room, so invites to an earlier room that had the same
+    /// code don't let anyone in
+    pub invite_nonce: u64,
+    /// results of earlier rounds in this room, oldest first, without the
+    /// emotes each player was shown
+    pub past_rounds: Vec<GameOverData>,
+}
+
+/// Who can find and join a room
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomVisibility {
+    /// listed in the room browser
+    Public,
+    /// anyone with the code can join
+    #[default]
+    Unlisted,
+    /// not listed, and closed to new players
+    Private,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    /// everyone guesses their own emotes
+    #[default]
+    Classic,
+    /// the owner and their chat race each other on the same emotes; anyone
+    /// else joining the room watches
+    StreamerVsChat,
+    /// everyone is on the same emote, and the room moves on together once
+    /// somebody guesses it
+    Race,
+}
+
+/// The emote a [`GameMode::Race`] room is on
+#[derive(Debug, Default)]
+pub struct RaceRound {
+    pub emote: u32,
+    /// players who guessed it, first first
+    pub solvers: Vec<User>,
+    /// players who gave up on it
+    pub passed: HashSet<User>,
+    /// whether runners-up are being waited for
+    pub window_open: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Spectator {
+    /// player whose emotes are forwarded to the spectator
+    pub following: Option<User>,
+    /// how long forwarded emotes are held back, so a stream overlay doesn't
+    /// give away what the player is on
+    pub delay: Duration,
+}
+
+/// Twitch chat, guessing its own emotes; whoever in chat gets one first
+/// scores it
+#[derive(Debug, Default)]
+pub struct ChatTeam {
+    pub emote: u32,
+    /// emotes chat has guessed
+    pub correct: u32,
+    /// by chatter login
+    pub scores: HashMap<String, f32>,
+    /// reads the chat while a game is running
+    pub task: Option<JoinHandle<()>>,
 }
 
 /// A cloneable GameState "view"; enough for identification purposes
@@ -38,17 +172,55 @@ pub struct GameStateView {
     pub room_owner: User,
 }
 
+/// What happened to an emote a player was shown
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmoteOutcome {
+    Guessed,
+    Skipped,
+    /// still on screen when the game ended
+    Unfinished,
+    /// chat guessed it first, see [`GameMode::StreamerVsChat`]
+    TakenByChat,
+    /// somebody else won the race for it
+    Missed,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct UserGameData {
     pub score: f32,
     pub emote: u32,
+    pub wrong: u32,
+    /// correct guesses in a row, right now
+    pub combo: u32,
+    pub best_combo: u32,
+    /// joined after the game had started
+    pub late: bool,
+    /// index of the first emote the player was shown; late joiners of a race
+    /// start on the race's emote
+    pub first_emote: u32,
+    /// outcome of every emote from `first_emote` up to the current one
+    pub history: Vec<EmoteOutcome>,
+    /// how far into the game the player reached the round limit
+    pub finished_at: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct Connection {
+    pub ws: SplitSink<WebSocket, Message>,
+    pub read_only: bool,
 }
 
 #[derive(Debug)]
 pub struct UserData {
     pub user: User,
     pub claim: JWTClaim,
-    pub ws: SplitSink<WebSocket, Message>,
+    /// every socket the user has open, by connection ID; empty while the
+    /// user is disconnected but may still come back
+    pub connections: HashMap<u64, Connection>,
+    /// times the user's last connection has closed; a grace period only
+    /// ends the session if there hasn't been another since it started
+    pub disconnects: u64,
 }
 
 #[derive(Debug)]
@@ -56,33 +228,374 @@ pub struct AppData {
     pub game_states: Arc<RwLock<HashMap<RoomID, GameState>>>,
     pub users: Arc<RwLock<HashMap<User, UserData>>>,
     pub jwt: Arc<JWTManager<HS256Key>>,
+    pub emotes: Arc<EmoteProviders>,
+    pub db: Arc<Database>,
+    /// users who asked to be told whenever the public room list changes
+    pub lobby_subscribers: Arc<RwLock<HashSet<User>>>,
+    /// Twitch IRC server, see [`crate::twitch_chat`]
+    pub chat_server: String,
+    /// limits how many room passwords are checked at once
+    pub password_checks: Semaphore,
+    connection_counter: AtomicU64,
 }
 pub type AppDataSync = Arc<AppData>;
 
 impl AppData {
-    pub fn new(key: HS256Key) -> Self {
+    pub fn new(key: HS256Key, emotes: EmoteProviders, db: Database, chat_server: String) -> Self {
         AppData {
             game_states: Default::default(),
             users: Default::default(),
-            jwt: JWTManager::<_>::new(key).into()
+            jwt: JWTManager::<_>::new(key).into(),
+            emotes: emotes.into(),
+            db: db.into(),
+            lobby_subscribers: Default::default(),
+            chat_server,
+            password_checks: Semaphore::new(MAX_PASSWORD_CHECKS),
+            connection_counter: AtomicU64::new(0),
         }
     }
+
+    pub fn next_connection_id(&self) -> u64 {
+        self.connection_counter.fetch_add(1, Ordering::Relaxed)
+    }
 }
 
 impl GameState {
-    pub fn new(room_id: RoomID, owner_id: User, duration: Duration, seed: u64) -> Self {
+    pub fn new(
+        room_id: RoomID,
+        owner_id: User,
+        emote_sources: Vec<EmoteSource>,
+        duration: Duration,
+        seed: u64,
+    ) -> Self {
         Self {
             room_id,
             room_owner: owner_id.to_owned(),
+            visibility: RoomVisibility::default(),
+            mode: GameMode::default(),
+            password_hash: None,
+            allowlist: None,
+            banned: HashSet::new(),
+            muted: HashSet::new(),
+            max_players: None,
+            co_host: None,
             user_data: HashMap::from([(owner_id.clone(), Default::default())]),
-            emote_set_id: Default::default(),
+            join_order: vec![owner_id.clone()],
+            teams: vec![],
+            team_of: HashMap::new(),
+            team_hints: false,
+            spectators: HashMap::new(),
+            emote_sources,
             duration,
+            round_limit: RoundLimit::default(),
             seed,
+            scoring: ScoringRules::default(),
+            late_join: LateJoinRules::default(),
+            race_rules: RaceRules::default(),
+            race: RaceRound::default(),
+            twitch_chat: false,
+            chat: ChatTeam::default(),
             timer_handle: None,
+            started_at: None,
+            invite_nonce: rand::random(),
+            past_rounds: vec![],
+        }
+    }
+
+    /// Adds the player to the room, and to whichever team is smallest
+    pub fn add_player(&mut self, user: User) {
+        if self.user_data.insert(user.clone(), Default::default()).is_none() {
+            if let Some(team) = self.smallest_team() {
+                self.team_of.insert(user.clone(), team);
+            }
+            self.join_order.push(user);
+        }
+    }
+
+    /// Takes the player out of the room, handing ownership on if they had
+    /// it: to the co-host, or else whoever has been here longest. Returns
+    /// whether they were in the room at all.
+    pub fn remove_player(&mut self, user: &User) -> bool {
+        if self.user_data.remove(user).is_none() {
+            return false;
+        }
+
+        self.join_order.retain(|u| u != user);
+        self.team_of.remove(user);
+        if self.co_host.as_ref() == Some(user) {
+            self.co_host = None;
+        }
+
+        if self.room_owner == *user {
+            if let Some(next_owner) = self.co_host.take().or(self.join_order.first().cloned()) {
+                self.room_owner = next_owner;
+            }
+        }
+
+        true
+    }
+
+    /// Makes spectators of every player but the owner, who plays streamer vs
+    /// chat alone
+    pub fn spectate_all_but_owner(&mut self) {
+        let viewers: Vec<User> = self
+            .user_data
+            .keys()
+            .filter(|user| **user != self.room_owner)
+            .cloned()
+            .collect();
+        for viewer in viewers {
+            self.remove_player(&viewer);
+            self.spectators.insert(
+                viewer,
+                Spectator {
+                    following: None,
+                    delay: Duration::ZERO,
+                },
+            );
+        }
+    }
+
+    pub fn is_muted(&self, login: &str) -> bool {
+        self.muted.contains(&login.to_lowercase())
+    }
+
+    /// Whether another player can join, going by the player limit
+    pub fn is_full(&self) -> bool {
+        self.max_players.is_some_and(|max| self.user_data.len() >= max as usize)
+    }
+
+    /// Replaces the teams and shares the players out between them at random
+    pub fn set_teams(&mut self, teams: Vec<String>, rng: &mut impl Rng) {
+        self.teams = teams;
+        self.team_of.clear();
+        if self.teams.is_empty() {
+            return;
+        }
+
+        let mut players = self.join_order.clone();
+        players.shuffle(rng);
+        for (i, player) in players.into_iter().enumerate() {
+            self.team_of.insert(player, i % self.teams.len());
+        }
+    }
+
+    fn smallest_team(&self) -> Option<usize> {
+        (0..self.teams.len())
+            .min_by_key(|team| self.team_of.values().filter(|t| *t == team).count())
+    }
+
+    /// Everyone else on the player's team
+    pub fn teammates_of(&self, user: &User) -> Vec<User> {
+        let Some(team) = self.team_of.get(user) else {
+            return vec![];
+        };
+
+        self.team_of
+            .iter()
+            .filter(|(teammate, t)| *t == team && *teammate != user)
+            .map(|(teammate, _)| teammate.clone())
+            .collect()
+    }
+
+    /// Players on each team, in the order of `teams`
+    pub fn team_members(&self) -> Vec<Vec<User>> {
+        let mut members = vec![vec![]; self.teams.len()];
+        for user in &self.join_order {
+            if let Some(team) = self.team_of.get(user) {
+                members[*team].push(user.clone());
+            }
+        }
+        members
+    }
+
+    pub fn remove_spectator(&mut self, user: &User) -> bool {
+        self.spectators.remove(user).is_some()
+    }
+
+    /// Players and spectators
+    pub fn members(&self) -> Vec<User> {
+        self.user_data
+            .keys()
+            .chain(self.spectators.keys())
+            .cloned()
+            .collect()
+    }
+
+    /// Spectators following `player`, with their delay
+    pub fn followers_of(&self, player: &User) -> Vec<(User, Duration)> {
+        self.spectators
+            .iter()
+            .filter(|(_, spectator)| spectator.following.as_ref() == Some(player))
+            .map(|(user, spectator)| (user.clone(), spectator.delay))
+            .collect()
+    }
+
+    /// `new_owner` must already be in the room
+    pub fn transfer_ownership(&mut self, new_owner: User) {
+        if self.co_host.as_ref() == Some(&new_owner) {
+            self.co_host = None;
+        }
+        self.room_owner = new_owner;
+    }
+
+    /// Time left in the running game, if there is one and it's timed
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.timer_handle.as_ref()?;
+        if self.round_limit != RoundLimit::Timer {
+            return None;
+        }
+        let elapsed = self.started_at?.elapsed().unwrap_or_default();
+        Some(self.duration.saturating_sub(elapsed))
+    }
+
+    /// Whether the round limit has been reached, by everyone or by the first
+    /// to get there depending on the limit. In streamer vs chat only the
+    /// streamer plays, against chat.
+    pub fn round_over(&self) -> bool {
+        let mut players = self.user_data.iter().filter(|(user, _)| {
+            self.mode != GameMode::StreamerVsChat || **user == self.room_owner
+        });
+        match self.round_limit {
+            RoundLimit::Timer => false,
+            RoundLimit::Emotes { .. } => {
+                !self.is_empty() && players.all(|(_, p)| p.finished(self.round_limit))
+            }
+            RoundLimit::FirstTo { correct } => {
+                (self.mode == GameMode::StreamerVsChat && self.chat.correct >= correct)
+                    || players.any(|(_, p)| p.finished(self.round_limit))
+            }
+        }
+    }
+
+    /// Whether there are no players left; spectators don't keep a room open
+    pub fn is_empty(&self) -> bool {
+        self.user_data.is_empty()
+    }
+
+    pub fn archive_round(&mut self, mut results: GameOverData) {
+        // they go out with every room update, so only the scores are kept
+        for player in &mut results.standings {
+            player.emotes.clear();
+        }
+        if self.past_rounds.len() >= MAX_PAST_ROUNDS {
+            self.past_rounds.remove(0);
+        }
+        self.past_rounds.push(results);
+    }
+
+    /// Gets the room ready for another round with the same players
+    pub fn reset_round(&mut self, seed: u64) {
+        for user_game_data in self.user_data.values_mut() {
+            *user_game_data = UserGameData::default();
+        }
+        self.chat = ChatTeam::default();
+        self.race = RaceRound::default();
+        self.seed = seed;
+        self.started_at = None;
+    }
+
+    /// Whether the race should move on: everyone who can still score has,
+    /// or every player has guessed or given up
+    pub fn race_done(&self) -> bool {
+        self.race.solvers.len() >= self.race_rules.scoring_places()
+            || self.user_data.keys().all(|user| self.race_finished_by(user))
+    }
+
+    /// Whether the player has guessed or given up on the race's emote
+    pub fn race_finished_by(&self, user: &User) -> bool {
+        self.race.solvers.contains(user) || self.race.passed.contains(user)
+    }
+
+    /// Moves every player on to the next emote of the race, recording how
+    /// they did on this one. Returns who guessed it, first first.
+    pub fn advance_race(&mut self) -> Vec<User> {
+        let solvers = std::mem::take(&mut self.race.solvers);
+        let passed = std::mem::take(&mut self.race.passed);
+
+        for (user, user_game_data) in self.user_data.iter_mut() {
+            let outcome = if solvers.contains(user) {
+                EmoteOutcome::Guessed
+            } else if passed.contains(user) {
+                EmoteOutcome::Skipped
+            } else {
+                user_game_data.break_combo();
+                EmoteOutcome::Missed
+            };
+            user_game_data.history.push(outcome);
+            user_game_data.emote += 1;
+        }
+
+        self.race.emote += 1;
+        self.race.window_open = false;
+        solvers
+    }
+
+    pub fn chat_playing(&self) -> bool {
+        self.twitch_chat || self.mode == GameMode::StreamerVsChat
+    }
+
+    /// Seed for chat's emotes. Chat races the streamer on the same emotes in
+    /// streamer vs chat; otherwise they come in a different order from the
+    /// players' so chat can't read them off the streamer's screen.
+    pub fn chat_seed(&self) -> u64 {
+        match self.mode {
+            GameMode::StreamerVsChat => self.seed,
+            GameMode::Classic | GameMode::Race => self.seed.rotate_left(32),
+        }
+    }
+
+    /// In streamer vs chat, moves chat on to whichever emote the owner is on
+    pub fn sync_chat_emote(&mut self) {
+        if self.mode != GameMode::StreamerVsChat {
+            return;
+        }
+        if let Some(owner) = self.user_data.get(&self.room_owner) {
+            self.chat.emote = owner.emote;
+        }
+    }
+
+    pub fn stop_chat(&mut self) {
+        if let Some(task) = self.chat.task.take() {
+            task.abort();
         }
     }
 }
 
+impl UserGameData {
+    pub fn correct(&self) -> u32 {
+        self.count(EmoteOutcome::Guessed)
+    }
+
+    pub fn skips(&self) -> u32 {
+        self.count(EmoteOutcome::Skipped)
+    }
+
+    /// Adds a correct guess to the combo, returning its new length
+    pub fn extend_combo(&mut self) -> u32 {
+        self.combo += 1;
+        self.best_combo = self.best_combo.max(self.combo);
+        self.combo
+    }
+
+    pub fn break_combo(&mut self) {
+        self.combo = 0;
+    }
+
+    /// Whether the player has reached `limit`; they get no more emotes
+    pub fn finished(&self, limit: RoundLimit) -> bool {
+        match limit {
+            RoundLimit::Timer => false,
+            RoundLimit::Emotes { count } => self.emote >= count,
+            RoundLimit::FirstTo { correct } => self.correct() >= correct,
+        }
+    }
+
+    fn count(&self, outcome: EmoteOutcome) -> u32 {
+        self.history.iter().filter(|o| **o == outcome).count() as u32
+    }
+}
+
 impl From<&GameState> for GameStateView {
     fn from(value: &GameState) -> Self {
         GameStateView {
@@ -91,3 +604,52 @@ impl From<&GameState> for GameStateView {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn streamer_vs_chat(round_limit: RoundLimit) -> GameState {
+        let mut game_state = GameState::new(
+            RoomID::from("ROOM".to_string()),
+            User("streamer".to_string()),
+            vec![],
+            Duration::from_secs(100),
+            0,
+        );
+        game_state.mode = GameMode::StreamerVsChat;
+        game_state.round_limit = round_limit;
+        game_state.add_player(User("viewer".to_string()));
+        game_state
+    }
+
+    #[test]
+    fn only_the_streamer_has_to_get_through_the_emotes() {
+        let mut game_state = streamer_vs_chat(RoundLimit::Emotes { count: 3 });
+        assert!(!game_state.round_over());
+
+        game_state.user_data.get_mut(&User("streamer".to_string())).unwrap().emote = 3;
+        assert!(game_state.round_over());
+    }
+
+    #[test]
+    fn chat_can_get_there_first() {
+        let mut game_state = streamer_vs_chat(RoundLimit::FirstTo { correct: 2 });
+        game_state.chat.correct = 1;
+        assert!(!game_state.round_over());
+
+        game_state.chat.correct = 2;
+        assert!(game_state.round_over());
+    }
+
+    #[test]
+    fn everyone_plays_outside_streamer_vs_chat() {
+        let mut game_state = streamer_vs_chat(RoundLimit::Emotes { count: 3 });
+        game_state.mode = GameMode::Classic;
+        game_state.user_data.get_mut(&User("streamer".to_string())).unwrap().emote = 3;
+        assert!(!game_state.round_over());
+
+        game_state.user_data.get_mut(&User("viewer".to_string())).unwrap().emote = 3;
+        assert!(game_state.round_over());
+    }
+}
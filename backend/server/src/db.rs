@@ -0,0 +1,510 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::data::EmoteOutcome;
+
+/// Schema changes, applied in order. Never edit one that has shipped, add a
+/// new one instead; `PRAGMA user_version` tracks how many have been applied.
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE players (
+        twitch_id TEXT PRIMARY KEY,
+        login TEXT NOT NULL,
+        display_name TEXT NOT NULL
+    );
+
+    CREATE TABLE games (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        room_id TEXT NOT NULL,
+        emote_set TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER NOT NULL,
+        duration_secs INTEGER NOT NULL
+    );
+    CREATE INDEX games_emote_set ON games (emote_set);
+
+    CREATE TABLE game_players (
+        game_id INTEGER NOT NULL REFERENCES games (id),
+        twitch_id TEXT NOT NULL REFERENCES players (twitch_id),
+        score REAL NOT NULL,
+        correct INTEGER NOT NULL,
+        wrong INTEGER NOT NULL,
+        skips INTEGER NOT NULL,
+        PRIMARY KEY (game_id, twitch_id)
+    );
+
+    CREATE TABLE game_emotes (
+        game_id INTEGER NOT NULL REFERENCES games (id),
+        twitch_id TEXT NOT NULL REFERENCES players (twitch_id),
+        position INTEGER NOT NULL,
+        emote_name TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        PRIMARY KEY (game_id, twitch_id, position)
+    );
+"#];
+
+#[derive(Debug, Error)]
+pub enum DatabaseError {
+    #[error("SQLite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[error("Database task failed: {0}")]
+    TaskError(#[from] tokio::task::JoinError),
+}
+
+#[derive(Debug, Clone)]
+pub struct FinishedPlayer {
+    pub twitch_id: String,
+    pub login: String,
+    pub display_name: String,
+    pub score: f32,
+    pub correct: u32,
+    pub wrong: u32,
+    pub skips: u32,
+    /// every emote the player was shown, in order
+    pub emotes: Vec<(String, EmoteOutcome)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FinishedGame {
+    pub room_id: String,
+    /// see [`crate::emotes::emote_set_key`]
+    pub emote_set: String,
+    pub started_at: SystemTime,
+    pub ended_at: SystemTime,
+    pub duration_secs: u64,
+    pub players: Vec<FinishedPlayer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaderboardEntry {
+    pub twitch_id: String,
+    pub login: String,
+    pub display_name: String,
+    pub games_played: u32,
+    pub total_score: f64,
+    pub best_score: f64,
+    pub correct: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmoteStat {
+    pub name: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerProfile {
+    pub twitch_id: String,
+    pub login: String,
+    pub display_name: String,
+    pub games_played: u32,
+    pub total_score: f64,
+    pub best_score: f64,
+    pub correct: u32,
+    pub wrong: u32,
+    pub skips: u32,
+    /// correct guesses out of all guesses
+    pub accuracy: f64,
+    /// emotes guessed most often
+    pub favourite_emotes: Vec<EmoteStat>,
+    /// emotes skipped or left unfinished most often
+    pub hardest_emotes: Vec<EmoteStat>,
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn outcome_name(outcome: EmoteOutcome) -> &'static str {
+    match outcome {
+        EmoteOutcome::Guessed => "guessed",
+        EmoteOutcome::Skipped => "skipped",
+        EmoteOutcome::Unfinished => "unfinished",
+        EmoteOutcome::TakenByChat => "taken_by_chat",
+        EmoteOutcome::Missed => "missed",
+    }
+}
+
+fn top_emotes(
+    conn: &Connection,
+    twitch_id: &str,
+    outcomes: &[EmoteOutcome],
+    limit: u32,
+) -> Result<Vec<EmoteStat>, rusqlite::Error> {
+    let placeholders = outcomes
+        .iter()
+        .map(|o| format!("'{}'", outcome_name(*o)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut statement = conn.prepare(&format!(
+        "SELECT emote_name, COUNT(*) FROM game_emotes
+         WHERE twitch_id = ?1 AND outcome IN ({placeholders})
+         GROUP BY emote_name
+         ORDER BY COUNT(*) DESC, emote_name
+         LIMIT ?2"
+    ))?;
+
+    statement
+        .query_map(params![twitch_id, limit], |row| {
+            Ok(EmoteStat {
+                name: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect()
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), rusqlite::Error> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        tracing::info!("Applying database migration {}", i + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Game history and leaderboards, in SQLite. The connection is blocking, so
+/// every query runs on the blocking thread pool.
+#[derive(Debug, Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DatabaseError> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
+
+        Ok(Database {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, DatabaseError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        Ok(tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await??)
+    }
+
+    /// Stores a finished game, returning its ID
+    pub async fn record_game(&self, game: FinishedGame) -> Result<i64, DatabaseError> {
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+
+            tx.execute(
+                "INSERT INTO games (room_id, emote_set, started_at, ended_at, duration_secs)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    game.room_id,
+                    game.emote_set,
+                    unix_secs(game.started_at),
+                    unix_secs(game.ended_at),
+                    game.duration_secs,
+                ],
+            )?;
+            let game_id = tx.last_insert_rowid();
+
+            for player in &game.players {
+                // logins and display names can change, keep the latest
+                tx.execute(
+                    "INSERT INTO players (twitch_id, login, display_name) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (twitch_id) DO UPDATE
+                     SET login = excluded.login, display_name = excluded.display_name",
+                    params![player.twitch_id, player.login, player.display_name],
+                )?;
+
+                tx.execute(
+                    "INSERT INTO game_players (game_id, twitch_id, score, correct, wrong, skips)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        game_id,
+                        player.twitch_id,
+                        player.score,
+                        player.correct,
+                        player.wrong,
+                        player.skips,
+                    ],
+                )?;
+
+                for (position, (emote_name, outcome)) in player.emotes.iter().enumerate() {
+                    tx.execute(
+                        "INSERT INTO game_emotes (game_id, twitch_id, position, emote_name, outcome)
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![
+                            game_id,
+                            player.twitch_id,
+                            position,
+                            emote_name,
+                            outcome_name(*outcome),
+                        ],
+                    )?;
+                }
+            }
+
+            tx.commit()?;
+            Ok(game_id)
+        })
+        .await
+    }
+
+    /// Players ranked by total score, over every game or only games played
+    /// with `emote_set`
+    pub async fn leaderboard(
+        &self,
+        emote_set: Option<String>,
+        limit: u32,
+    ) -> Result<Vec<LeaderboardEntry>, DatabaseError> {
+        self.with_connection(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT p.twitch_id, p.login, p.display_name, COUNT(*), SUM(gp.score),
+                        MAX(gp.score), SUM(gp.correct)
+                 FROM game_players gp
+                 JOIN players p ON p.twitch_id = gp.twitch_id
+                 JOIN games g ON g.id = gp.game_id
+                 WHERE ?1 IS NULL OR g.emote_set = ?1
+                 GROUP BY p.twitch_id
+                 ORDER BY SUM(gp.score) DESC
+                 LIMIT ?2",
+            )?;
+
+            statement
+                .query_map(params![emote_set, limit], |row| {
+                    Ok(LeaderboardEntry {
+                        twitch_id: row.get(0)?,
+                        login: row.get(1)?,
+                        display_name: row.get(2)?,
+                        games_played: row.get(3)?,
+                        total_score: row.get(4)?,
+                        best_score: row.get(5)?,
+                        correct: row.get(6)?,
+                    })
+                })?
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn player_profile(
+        &self,
+        login: String,
+        emote_limit: u32,
+    ) -> Result<Option<PlayerProfile>, DatabaseError> {
+        self.with_connection(move |conn| {
+            let player = conn
+                .query_row(
+                    "SELECT twitch_id, login, display_name FROM players WHERE login = ?1 LIMIT 1",
+                    params![login],
+                    |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?;
+
+            let (twitch_id, login, display_name) = match player {
+                Some(p) => p,
+                None => return Ok(None),
+            };
+
+            let (games_played, total_score, best_score, correct, wrong, skips) = conn.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(score), 0), COALESCE(MAX(score), 0),
+                        COALESCE(SUM(correct), 0), COALESCE(SUM(wrong), 0),
+                        COALESCE(SUM(skips), 0)
+                 FROM game_players WHERE twitch_id = ?1",
+                params![twitch_id],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get::<_, u32>(3)?,
+                        row.get::<_, u32>(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )?;
+
+            let accuracy = match correct + wrong {
+                0 => 0.0,
+                guesses => correct as f64 / guesses as f64,
+            };
+
+            Ok(Some(PlayerProfile {
+                favourite_emotes: top_emotes(
+                    conn,
+                    &twitch_id,
+                    &[EmoteOutcome::Guessed],
+                    emote_limit,
+                )?,
+                hardest_emotes: top_emotes(
+                    conn,
+                    &twitch_id,
+                    &[EmoteOutcome::Skipped, EmoteOutcome::Unfinished],
+                    emote_limit,
+                )?,
+                twitch_id,
+                login,
+                display_name,
+                games_played,
+                total_score,
+                best_score,
+                correct,
+                wrong,
+                skips,
+                accuracy,
+            }))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use EmoteOutcome::*;
+
+    fn player(login: &str, score: f32, emotes: &[(&str, EmoteOutcome)]) -> FinishedPlayer {
+        let count = |outcome| emotes.iter().filter(|(_, o)| *o == outcome).count() as u32;
+        FinishedPlayer {
+            twitch_id: format!("id-{login}"),
+            login: login.to_string(),
+            display_name: login.to_uppercase(),
+            score,
+            correct: count(Guessed),
+            wrong: 1,
+            skips: count(Skipped),
+            emotes: emotes
+                .iter()
+                .map(|(name, outcome)| (name.to_string(), *outcome))
+                .collect(),
+        }
+    }
+
+    fn game(emote_set: &str, players: Vec<FinishedPlayer>) -> FinishedGame {
+        FinishedGame {
+            room_id: "ABCDEF".to_string(),
+            emote_set: emote_set.to_string(),
+            started_at: UNIX_EPOCH,
+            ended_at: UNIX_EPOCH,
+            duration_secs: 100,
+            players,
+        }
+    }
+
+    /// alice plays one game with the 7TV set, bob plays that and one with
+    /// the BTTV set
+    async fn database() -> Database {
+        let db = Database::open(":memory:").unwrap();
+        db.record_game(game(
+            "7tv:set",
+            vec![
+                player(
+                    "alice",
+                    3.0,
+                    &[
+                        ("KEKW", Guessed),
+                        ("PogChamp", Guessed),
+                        ("KEKW", Guessed),
+                        ("Sadge", Skipped),
+                        ("monkaS", Unfinished),
+                    ],
+                ),
+                player("bob", 1.0, &[("KEKW", Guessed)]),
+            ],
+        ))
+        .await
+        .unwrap();
+        db.record_game(game("bttv:set", vec![player("bob", 4.0, &[])]))
+            .await
+            .unwrap();
+        db
+    }
+
+    fn logins(entries: &[LeaderboardEntry]) -> Vec<(&str, u32, f64)> {
+        entries
+            .iter()
+            .map(|e| (e.login.as_str(), e.games_played, e.total_score))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn leaderboard_covers_every_set() {
+        let leaderboard = database().await.leaderboard(None, 10).await.unwrap();
+
+        assert_eq!(logins(&leaderboard), [("bob", 2, 5.0), ("alice", 1, 3.0)]);
+        assert_eq!(leaderboard[0].best_score, 4.0);
+    }
+
+    #[tokio::test]
+    async fn leaderboard_for_one_set() {
+        let db = database().await;
+
+        let leaderboard = db
+            .leaderboard(Some("7tv:set".to_string()), 10)
+            .await
+            .unwrap();
+        assert_eq!(logins(&leaderboard), [("alice", 1, 3.0), ("bob", 1, 1.0)]);
+
+        let leaderboard = db
+            .leaderboard(Some("7tv:set".to_string()), 1)
+            .await
+            .unwrap();
+        assert_eq!(logins(&leaderboard), [("alice", 1, 3.0)]);
+    }
+
+    #[tokio::test]
+    async fn profile_has_accuracy_and_top_emotes() {
+        let profile = database()
+            .await
+            .player_profile("alice".to_string(), 5)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(profile.display_name, "ALICE");
+        assert_eq!((profile.correct, profile.wrong, profile.skips), (3, 1, 1));
+        assert_eq!(profile.accuracy, 0.75);
+
+        let stats = |emotes: &[EmoteStat]| {
+            emotes
+                .iter()
+                .map(|e| (e.name.clone(), e.count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            stats(&profile.favourite_emotes),
+            [("KEKW".to_string(), 2), ("PogChamp".to_string(), 1)]
+        );
+        assert_eq!(
+            stats(&profile.hardest_emotes),
+            [("Sadge".to_string(), 1), ("monkaS".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn profile_of_unknown_player() {
+        let profile = database()
+            .await
+            .player_profile("carol".to_string(), 5)
+            .await;
+
+        assert!(profile.unwrap().is_none());
+    }
+}
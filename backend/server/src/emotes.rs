@@ -0,0 +1,196 @@
+use std::{collections::HashSet, fmt::Display, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    bttv::BTTVProvider, cache::TtlCache, ffz::FFZProvider, local::LocalProvider,
+    seventv::SevenTVProvider, twitch::TwitchProvider,
+};
+
+const DEFAULT_EMOTE_SET_ID: &str = "01GN2QZDS0000BKRM8E4JJD3NV";
+const EMOTE_CACHE_TTL: Duration = Duration::from_secs(10800);
+const EMOTE_CACHE_CAPACITY: usize = 256;
+/// Longest a provider gets to answer; rooms wait on it to start
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FinalEmote {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Error)]
+pub enum EmoteError {
+    #[error("Response body extraction error")]
+    ResponseError,
+
+    #[error("Reqwest error when decoding response {0}")]
+    ReqwestError(#[from] reqwest::Error),
+
+    #[error("Provider is not configured: {0}")]
+    NotConfigured(&'static str),
+
+    #[error("IO error when reading emote pack {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid emote pack manifest: {0}")]
+    ManifestError(String),
+
+    #[error("Unknown error")]
+    Unknown,
+}
+
+/// HTTP client for the providers, so a hung API can't hold up a room
+pub fn provider_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(PROVIDER_TIMEOUT)
+        .build()
+        .expect("can build an HTTP client")
+}
+
+/// Somewhere a room can get emotes from
+pub trait EmoteProvider {
+    /// Fetches every emote in the set identified by `id`; what the ID means
+    /// is up to the provider (emote set, Twitch user, ...)
+    fn get_emotes(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Vec<FinalEmote>, EmoteError>> + Send;
+}
+
+/// A set of emotes from a single provider, as chosen by the room owner
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "provider", content = "id", rename_all = "snake_case")]
+pub enum EmoteSource {
+    /// 7TV emote set ID
+    #[serde(rename = "7tv")]
+    SevenTV(String),
+    /// BetterTTV channel emotes, by Twitch user ID
+    #[serde(rename = "bttv")]
+    BTTV(String),
+    /// FrankerFaceZ channel emotes, by Twitch user ID
+    #[serde(rename = "ffz")]
+    FFZ(String),
+    /// Twitch channel emotes, by broadcaster ID
+    TwitchChannel(String),
+    TwitchGlobal,
+    /// The emote pack on disk the server was started with
+    Local,
+}
+
+impl Display for EmoteSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmoteSource::SevenTV(id) => write!(f, "7tv:{id}"),
+            EmoteSource::BTTV(id) => write!(f, "bttv:{id}"),
+            EmoteSource::FFZ(id) => write!(f, "ffz:{id}"),
+            EmoteSource::TwitchChannel(id) => write!(f, "twitch:{id}"),
+            EmoteSource::TwitchGlobal => write!(f, "twitch:global"),
+            EmoteSource::Local => write!(f, "local"),
+        }
+    }
+}
+
+/// All the providers the server knows about, plus a cache in front of them
+pub struct EmoteProviders {
+    pub seventv: SevenTVProvider,
+    pub bttv: BTTVProvider,
+    pub ffz: FFZProvider,
+    pub twitch: TwitchProvider,
+    pub local: Option<LocalProvider>,
+    cache: TtlCache<EmoteSource, Vec<FinalEmote>>,
+}
+
+impl std::fmt::Debug for EmoteProviders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmoteProviders").finish_non_exhaustive()
+    }
+}
+
+impl EmoteProviders {
+    pub fn new(
+        seventv: SevenTVProvider,
+        bttv: BTTVProvider,
+        ffz: FFZProvider,
+        twitch: TwitchProvider,
+        local: Option<LocalProvider>,
+    ) -> Self {
+        EmoteProviders {
+            seventv,
+            bttv,
+            ffz,
+            twitch,
+            local,
+            cache: TtlCache::new(EMOTE_CACHE_TTL, EMOTE_CACHE_CAPACITY),
+        }
+    }
+
+    async fn fetch(&self, source: &EmoteSource) -> Result<Vec<FinalEmote>, EmoteError> {
+        match source {
+            EmoteSource::SevenTV(id) => self.seventv.get_emotes(id).await,
+            EmoteSource::BTTV(id) => self.bttv.get_emotes(id).await,
+            EmoteSource::FFZ(id) => self.ffz.get_emotes(id).await,
+            EmoteSource::TwitchChannel(id) => self.twitch.get_emotes(id).await,
+            EmoteSource::TwitchGlobal => self.twitch.get_global_emotes().await,
+            EmoteSource::Local => match &self.local {
+                Some(local) => local.get_emotes("").await,
+                None => Err(EmoteError::NotConfigured("local emote pack")),
+            },
+        }
+    }
+
+    /// What new rooms play with; the local pack when there is one, so the
+    /// game works without network access
+    pub fn default_sources(&self) -> Vec<EmoteSource> {
+        match self.local {
+            Some(_) => vec![EmoteSource::Local],
+            None => vec![EmoteSource::SevenTV(DEFAULT_EMOTE_SET_ID.to_string())],
+        }
+    }
+
+    pub async fn get_emotes(&self, source: &EmoteSource) -> Result<Arc<Vec<FinalEmote>>, EmoteError> {
+        self.cache.get_or_fetch(source, || self.fetch(source)).await
+    }
+
+    /// Drops the cached copy of the source and fetches it again
+    pub async fn refresh_emotes(
+        &self,
+        source: &EmoteSource,
+    ) -> Result<Arc<Vec<FinalEmote>>, EmoteError> {
+        self.cache.refresh(source, || self.fetch(source)).await
+    }
+
+    pub fn invalidate(&self, source: &EmoteSource) {
+        self.cache.invalidate(source);
+    }
+
+    /// Merges the emotes of every source, in order. When two sources have an
+    /// emote with the same name, the first one wins so guesses stay unambiguous.
+    pub async fn get_emotes_for_sources(
+        &self,
+        sources: &[EmoteSource],
+    ) -> Result<Vec<FinalEmote>, EmoteError> {
+        let mut names = HashSet::new();
+        let mut merged = vec![];
+
+        for source in sources {
+            for emote in self.get_emotes(source).await?.iter() {
+                if names.insert(emote.name.to_lowercase()) {
+                    merged.push(emote.clone());
+                }
+            }
+        }
+
+        Ok(merged)
+    }
+}
+
+/// A stable name for a combination of sources, for grouping stats by emote set
+pub fn emote_set_key(sources: &[EmoteSource]) -> String {
+    sources
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
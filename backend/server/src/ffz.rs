@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+use crate::emotes::{EmoteError, EmoteProvider, FinalEmote, provider_client};
+
+const FFZ_API_HOST: &str = "https://api.frankerfacez.com";
+
+#[derive(Deserialize, Debug)]
+struct FFZEmote {
+    name: String,
+    /// scale ("1", "2", "4") to image URL
+    urls: HashMap<String, String>,
+    /// same as `urls`, only present for animated emotes
+    #[serde(default)]
+    animated: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FFZSet {
+    emoticons: Vec<FFZEmote>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FFZRoomResponse {
+    /// ordered so the emote list is stable between fetches
+    sets: BTreeMap<String, FFZSet>,
+}
+
+/// Picks the largest available scale
+fn get_emote_url(urls: HashMap<String, String>) -> Option<String> {
+    urls.into_iter()
+        .max_by_key(|(scale, _)| scale.parse::<u32>().unwrap_or_default())
+        .map(|(_, url)| match url.strip_prefix("//") {
+            // the v1 API hands out protocol-relative URLs
+            Some(url) => format!("https://{url}"),
+            None => url,
+        })
+}
+
+#[derive(Debug, Clone)]
+pub struct FFZProvider {
+    client: reqwest::Client,
+    api_host: String,
+}
+
+impl Default for FFZProvider {
+    fn default() -> Self {
+        Self::new(FFZ_API_HOST.to_string())
+    }
+}
+
+impl FFZProvider {
+    pub fn new(api_host: String) -> Self {
+        FFZProvider {
+            client: provider_client(),
+            api_host,
+        }
+    }
+}
+
+impl EmoteProvider for FFZProvider {
+    /// `twitch_user_id` is the Twitch ID of the channel whose emotes to use
+    async fn get_emotes(&self, twitch_user_id: &str) -> Result<Vec<FinalEmote>, EmoteError> {
+        let response: FFZRoomResponse = self
+            .client
+            .get(format!("{}/v1/room/id/{}", self.api_host, twitch_user_id))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response
+            .sets
+            .into_values()
+            .flat_map(|set| set.emoticons)
+            .flat_map(|emote| {
+                let url = get_emote_url(emote.animated.unwrap_or(emote.urls))?;
+                Some(FinalEmote {
+                    name: emote.name,
+                    url,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::{Filter, http::StatusCode};
+
+    use super::*;
+
+    async fn mock_provider() -> FFZProvider {
+        let rooms = warp::path!("v1" / "room" / "id" / String).map(|id: String| {
+            let (body, status) = match id.as_str() {
+                "71092938" => (include_str!("../fixtures/ffz_room.json"), StatusCode::OK),
+                _ => (
+                    r#"{"error":"Not Found","status":404,"message":"No such room"}"#,
+                    StatusCode::NOT_FOUND,
+                ),
+            };
+            warp::reply::with_status(
+                warp::reply::with_header(body, "content-type", "application/json"),
+                status,
+            )
+        });
+        let (addr, server) = warp::serve(rooms).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        FFZProvider::new(format!("http://{addr}"))
+    }
+
+    #[tokio::test]
+    async fn parses_room_emotes() {
+        let emotes = mock_provider().await.get_emotes("71092938").await.unwrap();
+
+        let emotes: Vec<_> = emotes
+            .iter()
+            .map(|e| (e.name.as_str(), e.url.as_str()))
+            .collect();
+        assert_eq!(
+            emotes,
+            [
+                ("KEKW", "https://cdn.frankerfacez.com/emote/381875/4"),
+                // animated, largest scale, protocol-relative URL fixed up
+                (
+                    "PauseChamp",
+                    "https://cdn.frankerfacez.com/emote/720507/animated/2"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_room_is_an_error() {
+        let result = mock_provider().await.get_emotes("1").await;
+        assert!(matches!(
+            result,
+            Err(EmoteError::ReqwestError(e)) if e.status().is_some_and(|s| s.as_u16() == 404)
+        ));
+    }
+}
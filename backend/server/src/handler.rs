@@ -1,35 +1,62 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::Infallible,
     ops::{Deref, DerefMut},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use backend::{
-    data::{AppData, AppDataSync, GameState, GameStateView, RoomID, User, UserData, UserGameData},
-    jwt::JWTClaim,
+    data::{
+        AppData, AppDataSync, Connection, EmoteOutcome, GameMode, GameState, RoomID,
+        RoomVisibility, Spectator, User, UserData, UserGameData,
+    },
+    db::{FinishedGame, FinishedPlayer},
+    emotes::{EmoteSource, FinalEmote, emote_set_key},
+    jwt::{InviteClaim, JWTClaim, JWTClaimError},
     models::{
-        requests::{EditRoomData, JoinRoomData, Request, SkipData, StartGameData, SubmitGuessData},
+        requests::{
+            AssignTeamData, BanPlayerData, CreateInviteData, CreateRoomData, EditRoomData,
+            JoinRoomData, KickPlayerData, ListRoomsData, MutePlayerData, ReloadEmoteSetData,
+            Request, RestartGameData, SetCoHostData, SkipData, SpectateRoomData, StartGameData,
+            SubmitGuessData, TransferOwnershipData,
+        },
         responses::{
-            EmoteData, EmoteResponse, GameOverData, GameUpdateData, NewUserData, Response,
-            RoomJoinData,
+            EmoteData, EmoteResponse, ErrorData, ErrorDataType, GameOverData, GameUpdateData,
+            InviteData, NewUserData, PlayerResult, RaceSolvedData, Response, RevealedEmote,
+            RoomJoinData, RoomListData, RoomStatus, RoomSummary, SpectatedEmoteData, TeamHintData,
+            TeamResult, TeamScore, VersusData,
         },
     },
-    seventv::{FinalEmote, get_emote_for_emote_set_id},
+    password::{hash_password, verify_password},
+    results::{rank_standings, rank_teams},
+    room_code::{generate_room_code, vanity_room_code},
+    scoring::{Play, RoundLimit},
+    twitch_chat::{ChatMessage, ChatReader},
 };
 use futures_util::{SinkExt, stream::SplitSink};
 use rand::{Rng, SeedableRng, seq::IndexedRandom};
 use rand_chacha::ChaCha8Rng;
 use serde_json::to_string;
-use uuid::{Uuid, uuid};
 use warp::filters::ws::{Message, WebSocket, Ws};
 
-// TODO: temporary constant
-const EMOTE_SET_ID: &str = "01GN2QZDS0000BKRM8E4JJD3NV";
 const DEFAULT_DURATION_SEC: u64 = 100;
-const CORRECT_SCORE: f32 = 1.0;
-const INCORRECT_SCORE: f32 = -0.2;
-const SKIP_SCORE: f32 = -0.1;
+/// Same as the room screen allows
+const MAX_DURATION_SEC: u64 = 3000;
+/// How long a dropped connection has to come back before the user leaves
+const RECONNECT_GRACE_PERIOD_SEC: u64 = 30;
+const DEFAULT_INVITE_VALIDITY_SEC: u64 = 24 * 60 * 60;
+const MAX_INVITE_VALIDITY_SEC: u64 = 7 * 24 * 60 * 60;
+const MAX_PASSWORD_LEN: usize = 128;
+const MAX_ALLOWLIST_LEN: usize = 500;
+const MAX_TEAMS: usize = 8;
+const MAX_TEAM_NAME_LEN: usize = 32;
+const MAX_SPECTATOR_DELAY_SEC: u64 = 300;
+/// Times a dropped Twitch chat is reconnected to before giving up on it
+const CHAT_RECONNECT_ATTEMPTS: u32 = 3;
+const CHAT_RECONNECT_DELAY_SEC: u64 = 5;
+/// Games with another round limit still end after this many game durations,
+/// in case the players stall
+const ROUND_LIMIT_TIMEOUT_FACTOR: u32 = 10;
 
 /// Utilities (No WebSocket contact)
 
@@ -42,210 +69,418 @@ pub async fn is_room_exists(app_data: &AppData, room_id: RoomID) -> bool {
     game_states.get(&room_id).is_some()
 }
 
-pub async fn get_duration_for_room(app_data: &AppData, room_id: &RoomID) -> u64 {
-    let data = app_data.game_states.read().await;
-    data.get(room_id).unwrap().duration.as_secs()
+pub async fn is_user_owner_of_room(game_state: &GameState, user: User) -> bool {
+    game_state.room_owner == user
 }
 
-pub async fn is_user_in_room(game_state: &GameState, user: User) -> bool {
-    if let Some(_) = game_state.user_data.keys().filter(|u| **u == user).last() {
-        true
-    } else {
-        false
-    }
+pub fn find_player_by_login(
+    game_state: &GameState,
+    user_map: &HashMap<User, UserData>,
+    login: &str,
+) -> Option<User> {
+    game_state
+        .user_data
+        .keys()
+        .find(|user| {
+            user_map
+                .get(user)
+                .is_some_and(|u| u.claim.data.login.eq_ignore_ascii_case(login))
+        })
+        .cloned()
 }
 
-pub async fn is_user_owner_of_room(game_state: &GameState, user: User) -> bool {
-    game_state.room_owner == user
+/// Sends to every connection the user has open
+pub async fn reply_to_user(user_map: &mut HashMap<User, UserData>, user: User, message: Message) {
+    let user_data = match user_map.get_mut(&user) {
+        Some(m) => m,
+        None => return,
+    };
+
+    for connection in user_data.connections.values_mut() {
+        if let Err(e) = connection.ws.send(message.clone()).await {
+            tracing::warn!("Cannot send to user {}: {e}", user.0);
+        }
+    }
 }
 
-pub async fn reply_to_user(user_map: &mut HashMap<User, UserData>, user: User, message: Message) {
-    match user_map.get_mut(&user) {
-        Some(m) => m.ws.send(message).await.unwrap(),
+/// Sends to one of the user's connections only
+pub async fn reply_to_connection(
+    user_map: &mut HashMap<User, UserData>,
+    user: User,
+    connection: u64,
+    message: Message,
+) {
+    let connection = match user_map
+        .get_mut(&user)
+        .and_then(|m| m.connections.get_mut(&connection))
+    {
+        Some(c) => c,
         None => return,
     };
+
+    if let Err(e) = connection.ws.send(message).await {
+        tracing::warn!("Cannot send to user {}: {e}", user.0);
+    };
 }
 
-async fn create_room(app_data: &AppDataSync, user_id: User) -> Option<RoomID> {
-    let uuid = Uuid::new_v4();
-    let seed: u64 = rand::random();
+/// Fetches every source to make sure they exist and have something to guess
+async fn validate_emote_sources(app_data: &AppData, sources: &[EmoteSource]) -> Result<(), String> {
+    if sources.is_empty() {
+        return Err("Need at least one emote source".to_string());
+    }
 
-    if !is_user_exists(&app_data, user_id.clone()).await {
-        return None;
+    for source in sources {
+        match app_data.emotes.get_emotes(source).await {
+            Ok(emotes) if emotes.is_empty() => {
+                return Err(format!("Emote source {source} has no emotes"));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!("Cannot fetch emote source {source}: {e}");
+                return Err(format!("Cannot fetch emote source {source}"));
+            }
+        }
     }
 
-    let mut game_states = app_data.game_states.write().await;
-    game_states.insert(
-        RoomID(uuid.to_string()),
-        GameState::new(
-            RoomID(uuid.to_string()),
-            user_id.clone(),
-            Duration::from_secs(DEFAULT_DURATION_SEC),
-            seed,
-        ),
-    );
+    Ok(())
+}
 
-    Some(RoomID(uuid.to_string()))
+/// Builds the room update as seen by `user`
+pub fn room_join_data(
+    game_state: &GameState,
+    user_map: &HashMap<User, UserData>,
+    user: &User,
+) -> RoomJoinData {
+    let login = |user: &User| Some(user_map.get(user)?.claim.data.login.clone());
+    RoomJoinData {
+        room_id: game_state.room_id.clone(),
+        is_owner: game_state.room_owner == *user,
+        visibility: game_state.visibility,
+        has_password: game_state.password_hash.is_some(),
+        allowlist: game_state.allowlist.as_ref().map(|allowlist| {
+            let mut logins: Vec<_> = allowlist.iter().cloned().collect();
+            logins.sort();
+            logins
+        }),
+        max_players: game_state.max_players,
+        muted: {
+            let mut logins: Vec<_> = game_state.muted.iter().cloned().collect();
+            logins.sort();
+            logins
+        },
+        twitch_chat: game_state.twitch_chat,
+        mode: game_state.mode,
+        spectating: game_state.spectators.contains_key(user),
+        owner: login(&game_state.room_owner).unwrap_or_default(),
+        co_host: game_state.co_host.as_ref().and_then(login),
+        game_duration: game_state.duration.as_secs(),
+        time_remaining: game_state.time_remaining().map(|d| d.as_secs()),
+        emote_sources: game_state.emote_sources.clone(),
+        scoring: game_state.scoring,
+        late_join: game_state.late_join,
+        race: game_state.race_rules,
+        round_limit: game_state.round_limit,
+        teams: team_scores(game_state, user_map),
+        team_hints: game_state.team_hints,
+        past_rounds: game_state.past_rounds.clone(),
+        scores: game_state
+            .user_data
+            .iter()
+            .flat_map(|(user, user_game_data)| {
+                Some((
+                    user_map.get(user)?.claim.data.login.clone(),
+                    user_game_data.score,
+                ))
+            })
+            .collect(),
+    }
 }
 
-/// Utilities (With websocket contact)
+/// Makes a room under `code`, or a random code if there isn't one
+async fn create_room(
+    app_data: &AppDataSync,
+    user_id: User,
+    emote_sources: Vec<EmoteSource>,
+    code: Option<RoomID>,
+    visibility: RoomVisibility,
+) -> Result<RoomID, String> {
+    let seed: u64 = rand::random();
 
-async fn leave_all_rooms(app_data: &AppDataSync, user_id: User) {
     if !is_user_exists(&app_data, user_id.clone()).await {
-        return;
+        return Err("Unknown user".to_string());
     }
 
-    let mut rooms_to_kill = vec![];
-    let mut rooms_to_leave = vec![];
-    {
-        let game_states = app_data.game_states.read().await;
-        let users_to_login = {
-            let user_datas = app_data.users.read().await;
-            user_datas
-                .iter()
-                .map(|(user, data)| (user.clone(), data.claim.data.login.clone()))
-                .collect::<HashMap<_, _>>()
-        };
-        for game_state in game_states.values() {
-            if is_user_owner_of_room(game_state, user_id.clone()).await {
-                for user in game_state.user_data.keys() {
-                    reply_to_user(
-                        &mut (*app_data.users.write().await),
-                        user.clone(),
-                        Message::text(
-                            serde_json::to_string(&Response::Error(
-                                backend::models::responses::ErrorData {
-                                    error_type:
-                                        backend::models::responses::ErrorDataType::RoomDisbanded,
-                                    error_msg: "room owner left room".to_string(),
-                                },
-                            ))
-                            .unwrap(),
-                        ),
-                    )
-                    .await
-                }
-                rooms_to_kill.push(game_state.room_id.clone());
-            } else if is_user_in_room(game_state, user_id.clone()).await {
-                for user in game_state.user_data.keys() {
-                    reply_to_user(
-                        &mut (*app_data.users.write().await),
-                        user.clone(),
-                        Message::text(
-                            serde_json::to_string(&Response::RoomJoin(RoomJoinData {
-                                room_id: game_state.room_id.clone(),
-                                is_owner: game_state.room_owner == *user,
-                                game_duration: get_duration_for_room(
-                                    &app_data,
-                                    &game_state.room_id.clone(),
-                                )
-                                .await,
-                                scores: game_state
-                                    .user_data
-                                    .keys()
-                                    .cloned()
-                                    .flat_map(|p| {
-                                        Some((
-                                            users_to_login.get(&p)?.to_string(),
-                                            game_state.user_data.get(&p)?.score,
-                                        ))
-                                    })
-                                    .collect(),
-                            }))
-                            .unwrap(),
-                        ),
-                    )
-                    .await
+    let mut game_states = app_data.game_states.write().await;
+    let room_id = match code {
+        Some(code) if game_states.contains_key(&code) => {
+            return Err(format!("Room code {} is already taken", code.0));
+        }
+        Some(code) => code,
+        None => {
+            let mut rng = rand::rng();
+            loop {
+                let code = generate_room_code(&mut rng);
+                if !game_states.contains_key(&code) {
+                    break code;
                 }
-                rooms_to_leave.push(game_state.room_id.clone())
             }
         }
-    }
+    };
 
-    let mut game_states = app_data.game_states.write().await;
-    for room_id in rooms_to_kill {
-        game_states.remove(&room_id);
-    }
+    let mut game_state = GameState::new(
+        room_id.clone(),
+        user_id.clone(),
+        emote_sources,
+        Duration::from_secs(DEFAULT_DURATION_SEC),
+        seed,
+    );
+    game_state.visibility = visibility;
+    game_states.insert(room_id.clone(), game_state);
 
-    for room_id in rooms_to_leave {
-        game_states
-            .get_mut(&room_id)
-            .unwrap()
-            .user_data
-            .remove(&user_id.clone());
-    }
+    Ok(room_id)
 }
 
-/// Requires both locks in app_data
-async fn send_to_room<F, Fut>(app_data: AppDataSync, room_id: &RoomID, filter_fn: F)
-where
-    F: Fn(User) -> Fut,
-    Fut: Future<Output = Message>,
-{
-    let users = {
-        let game_states = app_data.game_states.write().await;
+/// Utilities (With websocket contact)
+
+pub async fn reply_error_to_user(
+    app_data: &AppDataSync,
+    user: User,
+    error_type: ErrorDataType,
+    error_msg: String,
+) {
+    reply_to_user(
+        &mut (*app_data.users.write().await),
+        user,
+        Message::text(
+            serde_json::to_string(&Response::Error(ErrorData {
+                error_type,
+                error_msg,
+            }))
+            .unwrap(),
+        ),
+    )
+    .await
+}
+
+/// Sends everyone in the room their view of the room
+async fn send_room_update(app_data: &AppDataSync, room_id: &RoomID) {
+    let messages = {
+        let game_states = app_data.game_states.read().await;
+        let users = app_data.users.read().await;
         let game_state = match game_states.get(room_id) {
-            Some(g) => g,
+            Some(gs) => gs,
             None => {
                 tracing::warn!("Room ID not found");
                 return;
             }
         };
 
-        game_state.user_data.keys().cloned().collect::<Vec<_>>()
+        game_state
+            .members()
+            .into_iter()
+            .map(|user| {
+                (
+                    user.clone(),
+                    Message::text(
+                        serde_json::to_string(&Response::RoomJoin(room_join_data(
+                            game_state, &users, &user,
+                        )))
+                        .unwrap(),
+                    ),
+                )
+            })
+            .collect::<Vec<_>>()
     };
 
-    for user in users {
-        let message = filter_fn(user.clone()).await;
-        let mut user_data = app_data.users.write().await;
-        let data = match user_data.get_mut(&user) {
-            Some(d) => d,
-            None => {
-                tracing::warn!("Cannot find user in global user map");
-                continue;
+    {
+        let mut users = app_data.users.write().await;
+        for (user, message) in messages {
+            reply_to_user(&mut users, user, message).await;
+        }
+    }
+
+    broadcast_room_list(app_data).await;
+}
+
+pub fn room_summary(game_state: &GameState, user_map: &HashMap<User, UserData>) -> RoomSummary {
+    let owner = user_map.get(&game_state.room_owner).map(|u| &u.claim.data);
+    RoomSummary {
+        room_id: game_state.room_id.clone(),
+        owner: owner.map(|o| o.login.clone()).unwrap_or_default(),
+        owner_display_name: owner.map(|o| o.display_name.clone()).unwrap_or_default(),
+        player_count: game_state.user_data.len(),
+        emote_sources: game_state.emote_sources.clone(),
+        emote_set: emote_set_key(&game_state.emote_sources),
+        game_duration: game_state.duration.as_secs(),
+        status: if game_state.timer_handle.is_some() {
+            RoomStatus::InGame
+        } else {
+            RoomStatus::Lobby
+        },
+        has_password: game_state.password_hash.is_some(),
+        max_players: game_state.max_players,
+        mode: game_state.mode,
+    }
+}
+
+/// Rooms shown in the room browser
+pub async fn public_rooms(app_data: &AppData) -> Vec<RoomSummary> {
+    let game_states = app_data.game_states.read().await;
+    let users = app_data.users.read().await;
+
+    game_states
+        .values()
+        .filter(|game_state| game_state.visibility == RoomVisibility::Public)
+        .map(|game_state| room_summary(game_state, &users))
+        .collect()
+}
+
+/// Sends the room list to everyone subscribed to the lobby
+async fn broadcast_room_list(app_data: &AppDataSync) {
+    let subscribers = app_data
+        .lobby_subscribers
+        .read()
+        .await
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let message = Message::text(
+        serde_json::to_string(&Response::RoomList(RoomListData {
+            rooms: public_rooms(app_data).await,
+        }))
+        .unwrap(),
+    );
+
+    let mut users = app_data.users.write().await;
+    for user in subscribers {
+        reply_to_user(&mut users, user, message.clone()).await;
+    }
+}
+
+/// Takes the user out of every room they are in. Rooms left empty are
+/// closed, everyone else gets the updated player list (and owner).
+async fn leave_all_rooms(app_data: &AppDataSync, user_id: User) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    let mut rooms_left = vec![];
+    let mut orphaned_spectators = vec![];
+    let mut closed_room = false;
+    {
+        let mut game_states = app_data.game_states.write().await;
+        game_states.retain(|room_id, game_state| {
+            if !game_state.remove_player(&user_id) {
+                game_state.remove_spectator(&user_id);
+                return true;
             }
-        };
 
-        let _ = data.ws.send(message).await;
+            if game_state.is_empty() {
+                if let Some(timer_handle) = game_state.timer_handle.take() {
+                    timer_handle.abort();
+                }
+                game_state.stop_chat();
+                orphaned_spectators.extend(game_state.spectators.keys().cloned());
+                closed_room = true;
+                return false;
+            }
+
+            rooms_left.push(room_id.clone());
+            true
+        });
+    }
+
+    for user in orphaned_spectators {
+        reply_error_to_user(
+            app_data,
+            user,
+            ErrorDataType::RoomDisbanded,
+            "every player left the room".to_string(),
+        )
+        .await;
+    }
+
+    // only now that the user is gone, so the player list is right
+    for room_id in rooms_left {
+        send_room_update(app_data, &room_id).await;
+        player_left_game(app_data, room_id).await;
+    }
+
+    if closed_room {
+        broadcast_room_list(app_data).await;
     }
 }
 
 /// Room Handlers
 
-pub async fn handle_create_room(app_data: AppDataSync, user_id: User) {
-    let room_id = match create_room(&app_data, user_id.clone()).await {
-        Some(r) => r,
-        None => return,
+pub async fn handle_create_room(app_data: AppDataSync, user_id: User, data: CreateRoomData) {
+    let emote_sources = data
+        .emote_sources
+        .unwrap_or_else(|| app_data.emotes.default_sources());
+
+    let login = app_data
+        .users
+        .read()
+        .await
+        .get(&user_id)
+        .map(|u| u.claim.data.login.clone())
+        .unwrap_or_default();
+    let code = match data
+        .code
+        .as_deref()
+        .map(|code| vanity_room_code(code, &login))
+        .transpose()
+    {
+        Ok(code) => code,
+        Err(e) => {
+            reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidRoomCode, e).await;
+            return;
+        }
     };
 
-    let user_login = {
-        let users = app_data.users.read().await;
-        let data = match users.get(&user_id) {
-            Some(user) => user,
-            None => {
-                tracing::warn!("Cannot find entry for user id: {}", user_id.0);
+    if let Err(e) = validate_emote_sources(&app_data, &emote_sources).await {
+        reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidEmoteSet, e).await;
+        return;
+    }
+
+    let visibility = data.visibility.unwrap_or_default();
+    let room_id =
+        match create_room(&app_data, user_id.clone(), emote_sources, code, visibility).await {
+            Ok(r) => r,
+            Err(e) => {
+                reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidRoomCode, e).await;
                 return;
             }
         };
 
-        data.claim.data.login.clone()
-    };
+    send_room_update(&app_data, &room_id).await
+}
 
-    // TODO: fix this
-    reply_to_user(
-        &mut (*app_data.users.write().await),
-        user_id.clone(),
-        Message::text(
-            serde_json::to_string(&Response::RoomJoin(RoomJoinData {
-                room_id: room_id.clone(),
-                is_owner: true,
-                game_duration: get_duration_for_room(&app_data, &room_id.clone()).await,
-                scores: HashMap::from([(user_login, 0.0)]),
-            }))
-            .unwrap(),
-        ),
-    )
-    .await
+pub async fn handle_list_rooms(app_data: AppDataSync, user_id: User, data: ListRoomsData) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    {
+        let mut lobby_subscribers = app_data.lobby_subscribers.write().await;
+        if data.subscribe {
+            lobby_subscribers.insert(user_id.clone());
+        } else {
+            lobby_subscribers.remove(&user_id);
+        }
+    }
+
+    let message = Message::text(
+        serde_json::to_string(&Response::RoomList(RoomListData {
+            rooms: public_rooms(&app_data).await,
+        }))
+        .unwrap(),
+    );
+    reply_to_user(&mut (*app_data.users.write().await), user_id, message).await;
 }
 
 pub async fn handle_edit_room(app_data: AppDataSync, user_id: User, data: EditRoomData) {
@@ -253,9 +488,20 @@ pub async fn handle_edit_room(app_data: AppDataSync, user_id: User, data: EditRo
         return;
     }
 
-    let (owner, player_list) = {
-        let mut game_states = app_data.game_states.write().await;
-        let game_state = match game_states.get_mut(&data.room_id) {
+    // these would change the running game under the players
+    let changes_game = data.mode.is_some()
+        || data.round_limit.is_some()
+        || data.emote_sources.is_some()
+        || data.teams.is_some()
+        || data.scoring.is_some()
+        || data.race.is_some();
+    // the duration is always sent, so it only changes the game if it differs
+    let duration = tokio::time::Duration::from_secs(data.game_duration);
+
+    // before anything costly, like fetching emote sets or hashing the password
+    let running = {
+        let game_states = app_data.game_states.read().await;
+        let game_state = match game_states.get(&data.room_id) {
             Some(gs) => gs,
             None => {
                 tracing::info!("Edit room attempted on room ID that doesn't exist");
@@ -267,333 +513,1781 @@ pub async fn handle_edit_room(app_data: AppDataSync, user_id: User, data: EditRo
             return;
         }
 
-        game_state.duration = tokio::time::Duration::from_secs(data.game_duration);
-        (
-            game_state.room_owner.clone(),
-            game_state
-                .user_data
-                .iter()
-                .map(|(user, data)| (user.clone(), data.score.clone()))
-                .collect::<Vec<_>>(),
-        )
-    };
-
-    let scores = {
-        let users = app_data.users.read().await;
-        player_list
-            .into_iter()
-            .flat_map(|(user, score)| Some((users.get(&user)?, score)))
-            .map(|(user_data, score): (&UserData, f32)| (user_data.claim.data.login.clone(), score))
-            .collect::<HashMap<_, _>>()
+        game_state.timer_handle.is_some() && (changes_game || game_state.duration != duration)
     };
 
-    let room_id = data.room_id.clone();
-    send_to_room(app_data.clone(), &room_id.clone(), |user| {
-        let owner = owner.clone();
-        let scores = scores.clone();
-        let room_id = room_id.clone();
-        let app_data = app_data.clone();
-
-        async move {
-            Message::text(
-                serde_json::to_string(&Response::RoomJoin(RoomJoinData {
-                    room_id: room_id.clone(),
-                    is_owner: user == owner,
-                    game_duration: get_duration_for_room(&app_data, &room_id).await,
-                    scores,
-                }))
-                .unwrap(),
-            )
-        }
-    })
-    .await;
-}
-
-pub async fn handle_join_room(app_data: AppDataSync, user_id: User, data: JoinRoomData) {
-    if !is_user_exists(&app_data, user_id.clone()).await {
+    if running {
+        reply_error_to_user(
+            &app_data,
+            user_id,
+            ErrorDataType::InvalidRoomSettings,
+            "Mode, duration, round limit, emote sources, teams, scoring and race rules can't \
+             change during a game"
+                .to_string(),
+        )
+        .await;
         return;
     }
 
-    if !is_room_exists(&app_data, data.room_id.clone()).await {
-        reply_to_user(
-            &mut (*app_data.users.write().await),
+    if !(1..=MAX_DURATION_SEC).contains(&data.game_duration) {
+        reply_error_to_user(
+            &app_data,
             user_id,
-            Message::text(
-                serde_json::to_string(&Response::Error(backend::models::responses::ErrorData {
-                    error_type: backend::models::responses::ErrorDataType::RoomJoinFailed,
-                    error_msg: "Room does not exist".to_string(),
-                }))
-                .unwrap(),
-            ),
+            ErrorDataType::InvalidRoomSettings,
+            format!("Games can last between 1 and {MAX_DURATION_SEC} seconds"),
         )
         .await;
         return;
     }
 
-    tracing::debug!("Causing {user_id:#?} to leave all rooms");
-    leave_all_rooms(&app_data, user_id.clone()).await;
+    if let Some(scoring) = &data.scoring {
+        if let Err(e) = scoring.validate() {
+            reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidScoringRules, e).await;
+            return;
+        }
+    }
+
+    if let Some(late_join) = &data.late_join {
+        if let Err(e) = late_join.validate() {
+            reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidScoringRules, e).await;
+            return;
+        }
+    }
+
+    if let Some(race) = &data.race {
+        if let Err(e) = race.validate() {
+            reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidScoringRules, e).await;
+            return;
+        }
+    }
+
+    if let Some(round_limit) = &data.round_limit {
+        if let Err(e) = round_limit.validate() {
+            reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidRoomSettings, e).await;
+            return;
+        }
+    }
+
+    if let Some(Err(e)) = data.teams.as_deref().map(validate_teams) {
+        reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidRoomSettings, e).await;
+        return;
+    }
+
+    let allowlist = match data.allowlist.as_deref().map(parse_allowlist) {
+        Some(Err(e)) => {
+            reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidRoomSettings, e).await;
+            return;
+        }
+        Some(Ok(allowlist)) => Some(allowlist),
+        None => None,
+    };
+
+    // `Some(None)` takes the password off
+    let password_hash = match data.password {
+        Some(password) if password.is_empty() => Some(None),
+        Some(password) if password.len() > MAX_PASSWORD_LEN => {
+            reply_error_to_user(
+                &app_data,
+                user_id,
+                ErrorDataType::InvalidRoomSettings,
+                format!("Password can be at most {MAX_PASSWORD_LEN} characters"),
+            )
+            .await;
+            return;
+        }
+        Some(password) => {
+            match tokio::task::spawn_blocking(move || hash_password(&password)).await {
+                Ok(Ok(hash)) => Some(Some(hash)),
+                e => {
+                    tracing::error!("Cannot hash room password: {e:?}");
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+
+    // validate before taking the lock, fetching the set can take a while
+    if let Some(emote_sources) = &data.emote_sources {
+        if let Err(e) = validate_emote_sources(&app_data, emote_sources).await {
+            reply_error_to_user(&app_data, user_id, ErrorDataType::InvalidEmoteSet, e).await;
+            return;
+        }
+    }
 
-    let (owner, users, scores, game_duration) = {
+    {
         let mut game_states = app_data.game_states.write().await;
         let game_state = match game_states.get_mut(&data.room_id) {
             Some(gs) => gs,
             None => {
-                tracing::warn!(
-                    "Cannot get game state after checking with is_room_exists, probably a async desync"
-                );
+                tracing::info!("Edit room attempted on room ID that doesn't exist");
                 return;
             }
         };
 
-        if game_state.timer_handle.is_some() {
-            tracing::warn!("Somebody tried to join after game has started...");
-            reply_to_user(
-                &mut (*app_data.users.write().await),
-                user_id,
-                Message::text(
-                    serde_json::to_string(&Response::Error(
-                        backend::models::responses::ErrorData {
-                            error_type: backend::models::responses::ErrorDataType::RoomJoinFailed,
-                            error_msg: "Room already started".to_string(),
-                        },
-                    ))
-                    .unwrap(),
-                ),
-            )
-            .await;
+        // the owner or the game may have changed while this was validated
+        if !is_user_owner_of_room(game_state, user_id.clone()).await
+            || ((changes_game || game_state.duration != duration)
+                && game_state.timer_handle.is_some())
+        {
             return;
         }
 
-        tracing::debug!("Causing {user_id:#?} to join room {:#?}", data.room_id);
-        game_state
-            .user_data
-            .try_insert(user_id.clone(), Default::default())
-            .unwrap();
-
-        tracing::debug!("Room now has {} players", game_state.user_data.len());
-        let owner = game_state.room_owner.clone();
-
-        let users = game_state
-            .user_data
-            .iter()
-            .map(|(user, user_data)| (user.clone(), user_data.score))
-            .collect::<HashMap<_, _>>();
-        let scores = {
-            let user_states = app_data.users.read().await;
-            users
-                .iter()
-                .flat_map(|(user, score)| match user_states.get(user) {
-                    Some(u) => Some((u.claim.data.login.clone(), score.clone())),
-                    None => None,
-                })
-                .collect::<HashMap<_, _>>()
-        };
-
-        let game_duration = game_state.duration.as_secs();
-        (owner, users, scores, game_duration)
-    };
+        game_state.duration = duration;
+        if let Some(emote_sources) = data.emote_sources {
+            game_state.emote_sources = emote_sources;
+        }
+        if let Some(scoring) = data.scoring {
+            game_state.scoring = scoring;
+        }
+        if let Some(late_join) = data.late_join {
+            game_state.late_join = late_join;
+        }
+        if let Some(visibility) = data.visibility {
+            game_state.visibility = visibility;
+        }
+        if let Some(password_hash) = password_hash {
+            game_state.password_hash = password_hash;
+        }
+        if let Some(allowlist) = allowlist {
+            game_state.allowlist = allowlist;
+        }
+        if let Some(max_players) = data.max_players {
+            game_state.max_players = (max_players > 0).then_some(max_players);
+        }
+        if let Some(twitch_chat) = data.twitch_chat {
+            game_state.twitch_chat = twitch_chat;
+        }
+        if let Some(mode) = data.mode {
+            game_state.mode = mode;
+            if mode == GameMode::StreamerVsChat {
+                game_state.spectate_all_but_owner();
+            }
+        }
+        if let Some(race) = data.race {
+            game_state.race_rules = race;
+        }
+        if let Some(round_limit) = data.round_limit {
+            game_state.round_limit = round_limit;
+        }
+        if let Some(teams) = data.teams {
+            let teams = teams.iter().map(|name| name.trim().to_string()).collect();
+            game_state.set_teams(teams, &mut rand::rng());
+        }
+        if let Some(team_hints) = data.team_hints {
+            game_state.team_hints = team_hints;
+        }
+    }
+
+    send_room_update(&app_data, &data.room_id).await;
+}
+
+fn validate_teams(teams: &[String]) -> Result<(), String> {
+    if teams.len() == 1 || teams.len() > MAX_TEAMS {
+        return Err(format!("A room can have between 2 and {MAX_TEAMS} teams"));
+    }
+
+    if teams
+        .iter()
+        .any(|name| name.trim().is_empty() || name.trim().len() > MAX_TEAM_NAME_LEN)
+    {
+        return Err(format!(
+            "Team names must be between 1 and {MAX_TEAM_NAME_LEN} characters"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Lowercase logins; an empty list means no allowlist
+fn parse_allowlist(logins: &[String]) -> Result<Option<HashSet<String>>, String> {
+    if logins.len() > MAX_ALLOWLIST_LEN {
+        return Err(format!(
+            "Allowlist can have at most {MAX_ALLOWLIST_LEN} logins"
+        ));
+    }
+
+    let allowlist: HashSet<String> = logins
+        .iter()
+        .map(|login| login.trim().to_lowercase())
+        .filter(|login| !login.is_empty())
+        .collect();
+
+    Ok((!allowlist.is_empty()).then_some(allowlist))
+}
+
+pub async fn handle_create_invite(app_data: AppDataSync, user_id: User, data: CreateInviteData) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    let room_nonce = {
+        let game_states = app_data.game_states.read().await;
+        let game_state = match game_states.get(&data.room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
+        }
+
+        game_state.invite_nonce
+    };
+
+    let expires_in_secs = data
+        .expires_in_secs
+        .unwrap_or(DEFAULT_INVITE_VALIDITY_SEC)
+        .clamp(1, MAX_INVITE_VALIDITY_SEC);
+    let invite = InviteClaim {
+        room_id: data.room_id.clone(),
+        room_nonce,
+    };
+    let token = match app_data.jwt.create_invite_token(
+        invite,
+        jwt_simple::prelude::Duration::from_secs(expires_in_secs),
+    ) {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::error!("Cannot create invite: {e}");
+            return;
+        }
+    };
+
+    reply_to_user(
+        &mut (*app_data.users.write().await),
+        user_id,
+        Message::text(
+            serde_json::to_string(&Response::Invite(InviteData {
+                room_id: data.room_id,
+                token,
+                expires_in_secs,
+            }))
+            .unwrap(),
+        ),
+    )
+    .await;
+}
+
+pub async fn handle_reload_emote_set(
+    app_data: AppDataSync,
+    user_id: User,
+    data: ReloadEmoteSetData,
+) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    let emote_sources = {
+        let game_states = app_data.game_states.read().await;
+        let game_state = match game_states.get(&data.room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
+        }
+
+        // players' emote indices would point at different emotes after a reload
+        if game_state.timer_handle.is_some() {
+            tracing::warn!("Refusing to reload emote set while the game is running");
+            return;
+        }
+
+        game_state.emote_sources.clone()
+    };
+
+    for source in &emote_sources {
+        let error_msg = match app_data.emotes.refresh_emotes(source).await {
+            Ok(emotes) if !emotes.is_empty() => continue,
+            Ok(_) => format!("Emote source {source} has no emotes"),
+            Err(e) => {
+                tracing::warn!("Cannot reload emote source {source}: {e}");
+                format!("Cannot fetch emote source {source}")
+            }
+        };
+
+        reply_error_to_user(
+            &app_data,
+            user_id,
+            ErrorDataType::InvalidEmoteSet,
+            error_msg,
+        )
+        .await;
+        return;
+    }
+
+    send_room_update(&app_data, &data.room_id).await
+}
+
+pub async fn handle_join_room(mut app_data: AppDataSync, user_id: User, data: JoinRoomData) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
 
-    for (user, _) in &users {
-        tracing::debug!("Informing {user:#?} the join");
+    if !is_room_exists(&app_data, data.room_id.clone()).await {
         reply_to_user(
             &mut (*app_data.users.write().await),
-            user.clone(),
+            user_id,
             Message::text(
-                serde_json::to_string(&Response::RoomJoin(RoomJoinData {
-                    room_id: data.room_id.clone(),
-                    is_owner: *user == owner,
-                    game_duration,
-                    scores: scores.clone(),
+                serde_json::to_string(&Response::Error(backend::models::responses::ErrorData {
+                    error_type: backend::models::responses::ErrorDataType::RoomJoinFailed,
+                    error_msg: "Room does not exist".to_string(),
                 }))
                 .unwrap(),
             ),
         )
         .await;
+        return;
+    }
+
+    // only the streamer plays streamer vs chat, everyone else watches
+    let watch_only = app_data
+        .game_states
+        .read()
+        .await
+        .get(&data.room_id)
+        .is_some_and(|gs| gs.mode == GameMode::StreamerVsChat && gs.room_owner != user_id);
+    if watch_only {
+        let spectate = SpectateRoomData {
+            room_id: data.room_id,
+            follow: None,
+            delay_secs: 0,
+            password: data.password,
+            invite: data.invite,
+        };
+        handle_spectate_room(app_data, user_id, spectate).await;
+        return;
+    }
+
+    let access = check_room_access(
+        &app_data,
+        &user_id,
+        &data.room_id,
+        data.password.clone(),
+        data.invite.as_deref(),
+    )
+    .await;
+    if let Err((error_type, error_msg)) = access {
+        reply_error_to_user(&app_data, user_id, error_type, error_msg).await;
+        return;
+    }
+
+    // already in it, e.g. after reloading the page; leaving to join again
+    // would hand the room on, or close it, and lose their score
+    let rejoined = {
+        let game_states = app_data.game_states.read().await;
+        match game_states.get(&data.room_id) {
+            Some(gs) if gs.user_data.contains_key(&user_id) => {
+                let mut messages = vec![Response::RoomJoin(room_join_data(
+                    gs,
+                    &*app_data.users.read().await,
+                    &user_id,
+                ))];
+                if gs.timer_handle.is_some() {
+                    messages.push(Response::GameStarted);
+                    if let Some(emote) = current_emote(&app_data, gs, &user_id).await {
+                        messages.push(Response::Emote(EmoteData { emote }));
+                    }
+                }
+                Some(messages)
+            }
+            _ => None,
+        }
+    };
+    if let Some(messages) = rejoined {
+        let mut users = app_data.users.write().await;
+        for message in messages {
+            let message = Message::text(serde_json::to_string(&message).unwrap());
+            reply_to_user(&mut users, user_id.clone(), message).await;
+        }
+        return;
+    }
+
+    // before leaving their current room, which may close it or hand it on
+    let refusal = app_data
+        .game_states
+        .read()
+        .await
+        .get(&data.room_id)
+        .and_then(|gs| join_refusal(gs, &user_id));
+    if let Some((error_type, error_msg)) = refusal {
+        reply_error_to_user(&app_data, user_id, error_type, error_msg).await;
+        return;
+    }
+
+    tracing::debug!("Causing {user_id:#?} to leave all rooms");
+    leave_all_rooms(&app_data, user_id.clone()).await;
+
+    let joined_late = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&data.room_id) {
+            Some(gs) => gs,
+            None => {
+                tracing::warn!(
+                    "Cannot get game state after checking with is_room_exists, probably a async desync"
+                );
+                return;
+            }
+        };
+
+        // checked again, the room may have changed while they left theirs
+        if let Some((error_type, error_msg)) = join_refusal(game_state, &user_id) {
+            drop(game_states);
+            reply_error_to_user(&app_data, user_id, error_type, error_msg).await;
+            return;
+        }
+
+        let in_game = game_state.timer_handle.is_some();
+
+        tracing::debug!("Causing {user_id:#?} to join room {:#?}", data.room_id);
+        game_state.add_player(user_id.clone());
+        if in_game {
+            let handicap = game_state.late_join.handicap;
+            let race_emote = (game_state.mode == GameMode::Race).then_some(game_state.race.emote);
+            if let Some(user_game_data) = game_state.user_data.get_mut(&user_id) {
+                user_game_data.late = true;
+                user_game_data.score = -handicap;
+                // straight onto the emote everyone else is racing for
+                if let Some(race_emote) = race_emote {
+                    user_game_data.first_emote = race_emote;
+                    user_game_data.emote = race_emote;
+                }
+            }
+        }
+
+        tracing::debug!("Room now has {} players", game_state.user_data.len());
+        in_game
+    };
+
+    // the room update carries the time remaining
+    send_room_update(&app_data, &data.room_id).await;
+    tracing::debug!("Done informing everyone");
+
+    if joined_late {
+        reply_to_user(
+            &mut (*app_data.users.write().await),
+            user_id.clone(),
+            Message::text(serde_json::to_string(&Response::GameStarted).unwrap()),
+        )
+        .await;
+        send_random_emote(&mut app_data, user_id, data.room_id).await;
+    }
+}
+
+/// Why the user can't join the room as a player right now, if they can't
+fn join_refusal(game_state: &GameState, user: &User) -> Option<(ErrorDataType, String)> {
+    if game_state.timer_handle.is_some() && !game_state.late_join.allowed {
+        tracing::warn!("Somebody tried to join after game has started...");
+        return Some((
+            ErrorDataType::RoomJoinFailed,
+            "Room already started".to_string(),
+        ));
+    }
+
+    if game_state.is_full() && !game_state.user_data.contains_key(user) {
+        return Some((ErrorDataType::RoomFull, "Room is full".to_string()));
+    }
+
+    None
+}
+
+/// Whether the user may join or spectate the room: banned users never can, an invite
+/// gets anyone else in, otherwise the room must not be private, they must be on the allowlist if
+/// there is one, and they must know the password if there is one
+async fn check_room_access(
+    app_data: &AppDataSync,
+    user_id: &User,
+    room_id: &RoomID,
+    password: Option<String>,
+    invite: Option<&str>,
+) -> Result<(), (ErrorDataType, String)> {
+    let banned = app_data
+        .game_states
+        .read()
+        .await
+        .get(room_id)
+        .is_some_and(|gs| gs.banned.contains(user_id));
+    if banned {
+        return Err((
+            ErrorDataType::Banned,
+            "You are banned from this room".to_string(),
+        ));
+    }
+
+    if let Some(invite) = invite {
+        let room_nonce = match app_data.game_states.read().await.get(room_id) {
+            Some(gs) => gs.invite_nonce,
+            None => {
+                return Err((
+                    ErrorDataType::RoomJoinFailed,
+                    "Room does not exist".to_string(),
+                ));
+            }
+        };
+
+        return match app_data
+            .jwt
+            .verify_invite_token(invite, room_id, room_nonce)
+        {
+            Ok(()) => Ok(()),
+            Err(JWTClaimError::WrongRoom) => Err((
+                ErrorDataType::InvalidInvite,
+                "Invite is for another room".to_string(),
+            )),
+            Err(e) => {
+                tracing::debug!("Rejected invite: {e}");
+                Err((
+                    ErrorDataType::InvalidInvite,
+                    "Invite is invalid or has expired".to_string(),
+                ))
+            }
+        };
+    }
+
+    let password_hash = {
+        let game_states = app_data.game_states.read().await;
+        let users = app_data.users.read().await;
+        let Some(game_state) = game_states.get(room_id) else {
+            return Err((
+                ErrorDataType::RoomJoinFailed,
+                "Room does not exist".to_string(),
+            ));
+        };
+
+        // rejoining a room you're already in, or already watching
+        if game_state.user_data.contains_key(user_id) || game_state.spectators.contains_key(user_id)
+        {
+            return Ok(());
+        }
+
+        if game_state.visibility == RoomVisibility::Private {
+            return Err((
+                ErrorDataType::RoomPrivate,
+                "Room is private, ask the owner for an invite".to_string(),
+            ));
+        }
+
+        if let Some(allowlist) = &game_state.allowlist {
+            let login = users
+                .get(user_id)
+                .map(|u| u.claim.data.login.to_lowercase());
+            if !login.is_some_and(|login| allowlist.contains(&login)) {
+                return Err((
+                    ErrorDataType::NotOnAllowlist,
+                    "You are not on this room's allowlist".to_string(),
+                ));
+            }
+        }
+
+        game_state.password_hash.clone()
+    };
+
+    let Some(password_hash) = password_hash else {
+        return Ok(());
+    };
+    let Some(password) = password else {
+        return Err((
+            ErrorDataType::PasswordRequired,
+            "Room needs a password".to_string(),
+        ));
+    };
+
+    let Ok(_permit) = app_data.password_checks.acquire().await else {
+        return Err((
+            ErrorDataType::RoomJoinFailed,
+            "Cannot check the password".to_string(),
+        ));
+    };
+    let correct = tokio::task::spawn_blocking(move || verify_password(&password, &password_hash))
+        .await
+        .unwrap_or(false);
+    if correct {
+        Ok(())
+    } else {
+        Err((ErrorDataType::WrongPassword, "Wrong password".to_string()))
+    }
+}
+
+fn choose_random_emote(emote: &Vec<FinalEmote>, seed: u64, emote_index: u32) -> FinalEmote {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    // "wastes" the random for up to emote_index times
+    for _ in 0..emote_index {
+        let _ = emote.choose(&mut rng).unwrap();
+    }
+
+    emote.choose(&mut rng).unwrap().clone()
+}
+
+/// The emote the user is currently on, name hidden
+async fn current_emote(
+    app_data: &AppData,
+    game_state: &GameState,
+    user: &User,
+) -> Option<EmoteResponse> {
+    let game_user_data = game_state.user_data.get(user)?;
+
+    let emotes = app_data
+        .emotes
+        .get_emotes_for_sources(&game_state.emote_sources)
+        .await
+        .inspect_err(|e| tracing::error!("{}", e))
+        .ok()?;
+    let emote = choose_random_emote(&emotes, game_state.seed, game_user_data.emote);
+
+    Some(EmoteResponse {
+        matched_chars: emote.name.as_bytes().iter().map(|_| 'ඬ').collect(),
+        url: emote.url,
+    })
+}
+
+/// Sends the user their current emote, and forwards it to whoever is
+/// spectating them
+async fn send_random_emote(app_data: &mut AppDataSync, user: User, room_id: RoomID) {
+    let game_states = app_data.game_states.read().await;
+    let game_state = match game_states.get(&room_id) {
+        Some(gs) => gs,
+        None => return,
+    };
+
+    let emote = match current_emote(app_data, game_state, &user).await {
+        Some(e) => e,
+        None => return,
+    };
+
+    let mut users = app_data.users.write().await;
+    let followers = game_state.followers_of(&user);
+    if !followers.is_empty() {
+        let login = users
+            .get(&user)
+            .map(|u| u.claim.data.login.clone())
+            .unwrap_or_default();
+        let message = Message::text(
+            serde_json::to_string(&Response::SpectatedEmote(SpectatedEmoteData {
+                login,
+                emote: EmoteResponse {
+                    matched_chars: emote.matched_chars.clone(),
+                    url: emote.url.clone(),
+                },
+            }))
+            .unwrap(),
+        );
+
+        for (spectator, delay) in followers {
+            let app_data = app_data.clone();
+            let message = message.clone();
+            tokio::task::spawn(async move {
+                tokio::time::sleep(delay).await;
+                reply_to_user(&mut (*app_data.users.write().await), spectator, message).await;
+            });
+        }
+    }
+
+    reply_to_user(
+        &mut users,
+        user,
+        Message::text(serde_json::to_string(&Response::Emote(EmoteData { emote })).unwrap()),
+    )
+    .await;
+}
+
+async fn inform_room_game_state(app_data: &mut AppDataSync, room_id: RoomID) {
+    let (scores, combos, chat_scores, versus, teams, users) = {
+        let game_states = app_data.game_states.read().await;
+        let user_data = app_data.users.read().await;
+        match game_states.get(&room_id) {
+            Some(gs) => {
+                let login = |user: &User| user_data.get(user).unwrap().claim.data.login.clone();
+                (
+                    gs.user_data
+                        .iter()
+                        .map(|(user, user_game_data)| (login(user), user_game_data.score))
+                        .collect::<HashMap<_, _>>(),
+                    gs.user_data
+                        .iter()
+                        .map(|(user, user_game_data)| (login(user), user_game_data.combo))
+                        .collect::<HashMap<_, _>>(),
+                    gs.chat.scores.clone(),
+                    versus_data(gs, &user_data),
+                    team_scores(gs, &user_data),
+                    gs.members(),
+                )
+            }
+            None => return,
+        }
+    };
+
+    for user in users {
+        reply_to_user(
+            &mut (*app_data.users.write().await),
+            user,
+            Message::text(
+                serde_json::to_string(&Response::GameUpdate(GameUpdateData {
+                    scores: scores.clone(),
+                    combos: combos.clone(),
+                    chat_scores: chat_scores.clone(),
+                    versus: versus.clone(),
+                    teams: teams.clone(),
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+    }
+}
+
+async fn send_random_emote_to_room(app_data: &mut AppDataSync, room_id: RoomID) {
+    let users = {
+        let game_states = app_data.game_states.read().await;
+        match game_states.get(&room_id) {
+            Some(gs) => gs.user_data.keys().cloned().collect::<Vec<User>>(),
+            None => return,
+        }
+    };
+
+    for user in users {
+        send_random_emote(app_data, user, room_id.clone()).await;
+    }
+}
+
+/// Every emote the player was shown, in order; the last one is the emote
+/// they were on when time ran out
+fn revealed_emotes(
+    user_game_data: &UserGameData,
+    emotes: &Vec<FinalEmote>,
+    seed: u64,
+) -> Vec<RevealedEmote> {
+    if emotes.is_empty() {
+        return vec![];
+    }
+
+    user_game_data
+        .history
+        .iter()
+        .copied()
+        // a player who finished the round was never shown another emote
+        .chain(
+            user_game_data
+                .finished_at
+                .is_none()
+                .then_some(EmoteOutcome::Unfinished),
+        )
+        .zip(user_game_data.first_emote..)
+        .map(|(outcome, index)| {
+            let emote = choose_random_emote(emotes, seed, index);
+            RevealedEmote {
+                name: emote.name,
+                url: emote.url,
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// The scoreboard for streamer vs chat; `None` in any other mode
+fn versus_data(game_state: &GameState, user_map: &HashMap<User, UserData>) -> Option<VersusData> {
+    if game_state.mode != GameMode::StreamerVsChat {
+        return None;
+    }
+
+    let streamer = &game_state.room_owner;
+    Some(VersusData {
+        streamer: user_map
+            .get(streamer)
+            .map(|u| u.claim.data.login.clone())
+            .unwrap_or_default(),
+        streamer_points: game_state
+            .user_data
+            .get(streamer)
+            .map(UserGameData::correct)
+            .unwrap_or_default(),
+        chat_points: game_state.chat.correct,
+    })
+}
+
+/// Final standings, sent to everyone in the room
+fn game_results(
+    game_state: &GameState,
+    user_map: &HashMap<User, UserData>,
+    emotes: &Vec<FinalEmote>,
+) -> GameOverData {
+    let standings = game_state
+        .user_data
+        .iter()
+        .flat_map(|(user, user_game_data)| {
+            let claim = &user_map.get(user)?.claim.data;
+            Some(PlayerResult {
+                rank: 0,
+                login: claim.login.clone(),
+                display_name: claim.display_name.clone(),
+                score: user_game_data.score,
+                correct: user_game_data.correct(),
+                skips: user_game_data.skips(),
+                wrong: user_game_data.wrong,
+                longest_combo: user_game_data.best_combo,
+                late: user_game_data.late,
+                time_taken_ms: user_game_data
+                    .finished_at
+                    .map(|finished_at| finished_at.as_millis() as u64),
+                emotes: revealed_emotes(user_game_data, emotes, game_state.seed),
+            })
+        })
+        .collect();
+
+    let teams = team_scores(game_state, user_map)
+        .into_iter()
+        .map(|team| TeamResult {
+            rank: 0,
+            name: team.name,
+            score: team.score,
+            players: team.players,
+        })
+        .collect();
+
+    GameOverData {
+        versus: versus_data(game_state, user_map),
+        teams: rank_teams(teams),
+        ..rank_standings(standings)
+    }
+}
+
+/// Each team's players and total score, in the order of the room's teams
+fn team_scores(game_state: &GameState, user_map: &HashMap<User, UserData>) -> Vec<TeamScore> {
+    game_state
+        .teams
+        .iter()
+        .zip(game_state.team_members())
+        .map(|(name, members)| TeamScore {
+            name: name.clone(),
+            score: members
+                .iter()
+                .flat_map(|user| game_state.user_data.get(user))
+                .map(|user_game_data| user_game_data.score)
+                .sum(),
+            players: members
+                .iter()
+                .flat_map(|user| Some(user_map.get(user)?.claim.data.login.clone()))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Snapshot of a finished game for the history database
+fn finished_game(
+    game_state: &GameState,
+    user_map: &HashMap<User, UserData>,
+    emotes: &Vec<FinalEmote>,
+) -> FinishedGame {
+    let players = game_state
+        .user_data
+        .iter()
+        .flat_map(|(user, user_game_data)| {
+            let claim = &user_map.get(user)?.claim.data;
+
+            Some(FinishedPlayer {
+                twitch_id: claim.id.clone(),
+                login: claim.login.clone(),
+                display_name: claim.display_name.clone(),
+                score: user_game_data.score,
+                correct: user_game_data.correct(),
+                wrong: user_game_data.wrong,
+                skips: user_game_data.skips(),
+                emotes: revealed_emotes(user_game_data, emotes, game_state.seed)
+                    .into_iter()
+                    .map(|emote| (emote.name, emote.outcome))
+                    .collect(),
+            })
+        })
+        .collect();
+
+    let ended_at = SystemTime::now();
+    FinishedGame {
+        room_id: game_state.room_id.0.clone(),
+        emote_set: emote_set_key(&game_state.emote_sources),
+        started_at: game_state.started_at.unwrap_or(ended_at),
+        ended_at,
+        duration_secs: game_state.duration.as_secs(),
+        players,
+    }
+}
+
+/// Ends the game if it has reached its round limit, noting when players
+/// got to the limit on the way. Returns whether it ended.
+async fn end_game_if_done(app_data: &AppDataSync, room_id: &RoomID) -> bool {
+    {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(room_id) {
+            Some(gs) => gs,
+            None => return false,
+        };
+
+        if game_state.timer_handle.is_none() {
+            return false;
+        }
+
+        let elapsed = game_state
+            .started_at
+            .and_then(|started_at| started_at.elapsed().ok())
+            .unwrap_or_default();
+        let round_limit = game_state.round_limit;
+        for user_game_data in game_state.user_data.values_mut() {
+            if user_game_data.finished_at.is_none() && user_game_data.finished(round_limit) {
+                user_game_data.finished_at = Some(elapsed);
+            }
+        }
+
+        if !game_state.round_over() {
+            return false;
+        }
+
+        // whoever takes the handle ends the game, so it only ends once
+        match game_state.timer_handle.take() {
+            Some(timer_handle) => timer_handle.abort(),
+            None => return false,
+        }
+    }
+
+    handle_game_end(app_data.clone(), room_id.clone()).await;
+    true
+}
+
+/// Moves the game on after a player has gone, who may have been the last one
+/// it was waiting for: to finish the round, or to guess or pass on the race's
+/// emote
+async fn player_left_game(app_data: &AppDataSync, room_id: RoomID) {
+    if end_game_if_done(app_data, &room_id).await {
+        return;
+    }
+
+    let is_race = app_data
+        .game_states
+        .read()
+        .await
+        .get(&room_id)
+        .is_some_and(|gs| gs.mode == GameMode::Race);
+    if is_race {
+        progress_race(&mut app_data.clone(), room_id).await;
+    }
+}
+
+async fn is_player_finished(app_data: &AppDataSync, room_id: &RoomID, user: &User) -> bool {
+    let game_states = app_data.game_states.read().await;
+    game_states.get(room_id).is_some_and(|game_state| {
+        game_state
+            .user_data
+            .get(user)
+            .is_some_and(|user_game_data| user_game_data.finished(game_state.round_limit))
+    })
+}
+
+async fn handle_game_end(mut app_data: AppDataSync, room_id: RoomID) {
+    // fetched without holding the lock, it may go out to the network
+    let (emote_sources, started_at) = match app_data.game_states.read().await.get(&room_id) {
+        Some(gs) if gs.started_at.is_some() => (gs.emote_sources.clone(), gs.started_at),
+        _ => return,
+    };
+    let emotes = app_data.emotes.get_emotes_for_sources(&emote_sources).await;
+
+    // inform every user in the room that the game has ended
+    let (users, results, finished_game) = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        // the room may have restarted, and started a new game, in the meantime
+        if game_state.started_at != started_at {
+            return;
+        }
+
+        game_state.timer_handle = None;
+        game_state.stop_chat();
+        let user_map = app_data.users.read().await;
+        let emotes = emotes
+            .inspect_err(|e| tracing::error!("Cannot get emotes to record the game: {e}"))
+            .ok();
+        // without the emote set the standings go out with nothing revealed
+        let results = game_results(game_state, &user_map, emotes.as_ref().unwrap_or(&vec![]));
+        let finished_game = emotes.map(|emotes| finished_game(game_state, &user_map, &emotes));
+        game_state.archive_round(results.clone());
+
+        (game_state.members(), results, finished_game)
+    };
+
+    broadcast_room_list(&app_data).await;
+
+    let message = serde_json::to_string(&Response::GameOver(results)).unwrap();
+    for user in users {
+        reply_to_user(
+            &mut (*app_data.users.write().await),
+            user.clone(),
+            Message::text(message.clone()),
+        )
+        .await
+    }
+
+    if let Some(finished_game) = finished_game {
+        if let Err(e) = app_data.db.record_game(finished_game).await {
+            tracing::error!("Cannot record game for room {}: {e}", room_id.0);
+        }
+    }
+}
+
+/// Watch a room without playing, at any point in the game
+pub async fn handle_spectate_room(
+    mut app_data: AppDataSync,
+    user_id: User,
+    data: SpectateRoomData,
+) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    if data.delay_secs > MAX_SPECTATOR_DELAY_SEC {
+        reply_error_to_user(
+            &app_data,
+            user_id,
+            ErrorDataType::RoomJoinFailed,
+            format!("Delay can be at most {MAX_SPECTATOR_DELAY_SEC} seconds"),
+        )
+        .await;
+        return;
+    }
+
+    // spectators see the scoreboard and live emotes, so they need to be let
+    // in just like players
+    let access = check_room_access(
+        &app_data,
+        &user_id,
+        &data.room_id,
+        data.password.clone(),
+        data.invite.as_deref(),
+    )
+    .await;
+    if let Err((error_type, error_msg)) = access {
+        reply_error_to_user(&app_data, user_id, error_type, error_msg).await;
+        return;
+    }
+
+    let already_spectating = {
+        let game_states = app_data.game_states.read().await;
+        match game_states.get(&data.room_id) {
+            Some(gs) => gs.spectators.contains_key(&user_id),
+            None => {
+                drop(game_states);
+                reply_error_to_user(
+                    &app_data,
+                    user_id,
+                    ErrorDataType::RoomJoinFailed,
+                    "Room does not exist".to_string(),
+                )
+                .await;
+                return;
+            }
+        }
+    };
+
+    // switching who to follow shouldn't take you out of the room
+    if !already_spectating {
+        leave_all_rooms(&app_data, user_id.clone()).await;
+    }
+
+    let (message, following) = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&data.room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+        let users = app_data.users.read().await;
+
+        let following = match &data.follow {
+            Some(login) => match find_player_by_login(game_state, &users, login) {
+                Some(player) => Some(player),
+                None => {
+                    drop(users);
+                    drop(game_states);
+                    reply_error_to_user(
+                        &app_data,
+                        user_id,
+                        ErrorDataType::PlayerNotInRoom,
+                        format!("{login} is not in this room"),
+                    )
+                    .await;
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        game_state.spectators.insert(
+            user_id.clone(),
+            Spectator {
+                following: following.clone(),
+                delay: Duration::from_secs(data.delay_secs),
+            },
+        );
+
+        let message = Message::text(
+            serde_json::to_string(&Response::RoomJoin(room_join_data(
+                game_state, &users, &user_id,
+            )))
+            .unwrap(),
+        );
+        let following = following.filter(|_| game_state.timer_handle.is_some());
+        (message, following)
+    };
+
+    reply_to_user(&mut (*app_data.users.write().await), user_id, message).await;
+
+    // mid-game, catch the spectator up on the emote the player is on now
+    if let Some(player) = following {
+        send_random_emote(&mut app_data, player, data.room_id).await;
+    }
+}
+
+pub async fn handle_start_game(mut app_data: AppDataSync, user_id: User, data: StartGameData) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    let is_room_owner = {
+        let game_states = app_data.game_states.read().await;
+        let game_state = match game_states.get(&data.room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        game_state.room_owner == user_id
+    };
+
+    if is_room_owner {
+        let reset = {
+            // update room seed
+            let mut game_states = app_data.game_states.write().await;
+            let game_state = match game_states.get_mut(&data.room_id) {
+                Some(gs) => gs,
+                None => return,
+            };
+            if game_state.timer_handle.is_some() {
+                tracing::info!("Start attempted while a game is running");
+                return;
+            }
+
+            let duration = game_state.duration;
+            let cloned_appdata = app_data.clone();
+            let cloned_roomid = data.room_id.clone();
+            let seed: u64 = rand::random();
+
+            // starting again without `restart_game` in between; the last
+            // round is archived already, so it's only cleared
+            let reset = game_state.started_at.is_some();
+            if reset {
+                game_state.reset_round(seed);
+            }
+
+            // other limits are checked as players go, see `end_game_if_done`
+            let duration = match game_state.round_limit {
+                RoundLimit::Timer => duration,
+                _ => duration.saturating_mul(ROUND_LIMIT_TIMEOUT_FACTOR),
+            };
+            game_state.timer_handle = Some(tokio::task::spawn(async move {
+                tokio::time::sleep(duration).await;
+                handle_game_end(cloned_appdata, cloned_roomid).await;
+            }));
+            game_state.seed = seed;
+            game_state.started_at = Some(SystemTime::now());
+
+            if game_state.chat_playing() {
+                let channel = app_data
+                    .users
+                    .read()
+                    .await
+                    .get(&user_id)
+                    .map(|u| u.claim.data.login.clone());
+                if let Some(channel) = channel {
+                    game_state.chat.task = Some(tokio::task::spawn(run_chat(
+                        app_data.clone(),
+                        data.room_id.clone(),
+                        channel,
+                    )));
+                }
+            }
+            reset
+        };
+
+        // everyone's scores went back to nothing
+        if reset {
+            send_room_update(&app_data, &data.room_id).await;
+        }
+
+        let spectators = app_data
+            .game_states
+            .read()
+            .await
+            .get(&data.room_id)
+            .map(|gs| gs.spectators.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        for spectator in spectators {
+            reply_to_user(
+                &mut (*app_data.users.write().await),
+                spectator,
+                Message::text(serde_json::to_string(&Response::GameStarted).unwrap()),
+            )
+            .await;
+        }
+        broadcast_room_list(&app_data).await;
+
+        send_chat_emote_to_room(&app_data, &data.room_id).await;
+        send_random_emote_to_room(&mut app_data, data.room_id).await
+    }
+}
+
+/// Moves a race on if its emote is done, or gives the runners-up their
+/// window once somebody has guessed it first
+async fn progress_race(app_data: &mut AppDataSync, room_id: RoomID) {
+    let window = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        if game_state.race_done() {
+            None
+        } else if !game_state.race.solvers.is_empty() && !game_state.race.window_open {
+            game_state.race.window_open = true;
+            Some((
+                game_state.race.emote,
+                Duration::from_secs(game_state.race_rules.window_secs),
+            ))
+        } else {
+            return;
+        }
+    };
+
+    match window {
+        None => {
+            advance_race(app_data, room_id, None).await;
+        }
+        Some((emote, window)) => {
+            let mut app_data = app_data.clone();
+            tokio::task::spawn(async move {
+                tokio::time::sleep(window).await;
+                if advance_race(&mut app_data, room_id.clone(), Some(emote)).await {
+                    inform_room_game_state(&mut app_data, room_id).await;
+                }
+            });
+        }
+    }
+}
+
+/// Ends the race's current emote, tells the room who got it and sends
+/// everyone the next one. With `emote`, only if the race is still on that
+/// emote, so a window closing late does nothing. Returns whether it did.
+async fn advance_race(app_data: &mut AppDataSync, room_id: RoomID, emote: Option<u32>) -> bool {
+    let (emote, started_at, emote_sources) = {
+        let game_states = app_data.game_states.read().await;
+        let game_state = match game_states.get(&room_id) {
+            Some(gs) => gs,
+            None => return false,
+        };
+
+        if game_state.timer_handle.is_none()
+            || emote.is_some_and(|emote| emote != game_state.race.emote)
+        {
+            return false;
+        }
+
+        (
+            game_state.race.emote,
+            game_state.started_at,
+            game_state.emote_sources.clone(),
+        )
+    };
+
+    // fetched without the lock, the set can take a while to download
+    let emotes = match app_data.emotes.get_emotes_for_sources(&emote_sources).await {
+        Ok(emotes) => emotes,
+        Err(e) => {
+            tracing::error!("{}", e);
+            return false;
+        }
+    };
+
+    let (solved, members) = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&room_id) {
+            Some(gs) => gs,
+            None => return false,
+        };
+
+        // a guess, the window or a new game may have moved the race on in the meantime
+        if game_state.timer_handle.is_none()
+            || game_state.started_at != started_at
+            || game_state.race.emote != emote
+        {
+            return false;
+        }
+
+        let emote = choose_random_emote(&emotes, game_state.seed, emote);
+
+        let solvers = game_state.advance_race();
+        let users = app_data.users.read().await;
+        let solved = RaceSolvedData {
+            name: emote.name,
+            url: emote.url,
+            solvers: solvers
+                .iter()
+                .flat_map(|user| Some(users.get(user)?.claim.data.login.clone()))
+                .collect(),
+        };
+
+        (solved, game_state.members())
+    };
+
+    let message = Message::text(serde_json::to_string(&Response::RaceSolved(solved)).unwrap());
+    {
+        let mut users = app_data.users.write().await;
+        for user in members {
+            reply_to_user(&mut users, user, message.clone()).await;
+        }
+    }
+
+    if !end_game_if_done(app_data, &room_id).await {
+        send_random_emote_to_room(app_data, room_id).await;
+    }
+    true
+}
+
+/// Treats messages in the channel's chat as guesses, until the game ends.
+/// Reconnects if the chat drops, and tells the owner once it gives up.
+async fn run_chat(mut app_data: AppDataSync, room_id: RoomID, channel: String) {
+    // emote sources can't change during a game, so the set is only fetched
+    // once rather than for every chat line
+    let emote_sources = match app_data.game_states.read().await.get(&room_id) {
+        Some(gs) => gs.emote_sources.clone(),
+        None => return,
+    };
+    let emotes = match app_data.emotes.get_emotes_for_sources(&emote_sources).await {
+        Ok(emotes) if !emotes.is_empty() => emotes,
+        Ok(_) => return,
+        Err(e) => {
+            tracing::error!("Cannot get emotes for the chat of {channel}: {e}");
+            return;
+        }
+    };
+
+    let mut attempts = 0;
+    loop {
+        match ChatReader::connect(&app_data.chat_server, &channel).await {
+            Ok(mut chat) => {
+                attempts = 0;
+                loop {
+                    match chat.next_message().await {
+                        Ok(message) => {
+                            handle_chat_guess(&mut app_data, &room_id, &emotes, message).await
+                        }
+                        Err(e) => {
+                            tracing::warn!("Lost the chat of {channel}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Cannot connect to the chat of {channel}: {e}"),
+        }
+
+        attempts += 1;
+        if attempts > CHAT_RECONNECT_ATTEMPTS {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(CHAT_RECONNECT_DELAY_SEC)).await;
+    }
+
+    tracing::error!("Giving up on the chat of {channel}");
+    let owner = app_data
+        .game_states
+        .read()
+        .await
+        .get(&room_id)
+        .map(|gs| gs.room_owner.clone());
+    if let Some(owner) = owner {
+        reply_error_to_user(
+            &app_data,
+            owner,
+            ErrorDataType::ChatUnavailable,
+            format!("Cannot reach the chat of {channel}"),
+        )
+        .await;
+    }
+}
+
+/// Chat only hears about right guesses; wrong ones are just chatter
+async fn handle_chat_guess(
+    app_data: &mut AppDataSync,
+    room_id: &RoomID,
+    emotes: &Vec<FinalEmote>,
+    message: ChatMessage,
+) {
+    let guess = message.text.trim().to_lowercase();
+
+    // most of chat is chatter, which only needs a look at the room
+    let emote = {
+        let game_states = app_data.game_states.read().await;
+        let game_state = match game_states.get(room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        if game_state.timer_handle.is_none() || game_state.is_muted(&message.login) {
+            return;
+        }
+
+        let target_emote =
+            choose_random_emote(emotes, game_state.chat_seed(), game_state.chat.emote);
+        if target_emote.name.to_lowercase() != guess {
+            return;
+        }
+        game_state.chat.emote
+    };
+
+    // the streamer, when chat just took their emote
+    let beaten_streamer = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        // somebody may have got there in between
+        if game_state.timer_handle.is_none() || game_state.chat.emote != emote {
+            return;
+        }
+
+        let points = game_state.scoring.points(Play::Correct { combo: 1 });
+        *game_state.chat.scores.entry(message.login).or_default() += points;
+        game_state.chat.emote += 1;
+        game_state.chat.correct += 1;
+
+        let streamer = game_state.room_owner.clone();
+        let beaten_streamer = match game_state.user_data.get_mut(&streamer) {
+            Some(user_data) if game_state.mode == GameMode::StreamerVsChat => {
+                user_data.emote += 1;
+                user_data.break_combo();
+                user_data.history.push(EmoteOutcome::TakenByChat);
+                Some(streamer)
+            }
+            _ => None,
+        };
+        game_state.sync_chat_emote();
+        beaten_streamer
+    };
+
+    if end_game_if_done(app_data, room_id).await {
+        return;
+    }
+
+    send_chat_emote_to_room(app_data, room_id).await;
+    if let Some(streamer) = beaten_streamer {
+        send_random_emote(app_data, streamer, room_id.clone()).await;
+    }
+    inform_room_game_state(app_data, room_id.clone()).await;
+}
+
+/// Shows everyone in the room the emote chat is on, if chat is playing
+async fn send_chat_emote_to_room(app_data: &AppDataSync, room_id: &RoomID) {
+    let (emote, members) = {
+        let game_states = app_data.game_states.read().await;
+        let game_state = match game_states.get(room_id) {
+            Some(gs) if gs.chat_playing() => gs,
+            _ => return,
+        };
+
+        let emotes = match app_data
+            .emotes
+            .get_emotes_for_sources(&game_state.emote_sources)
+            .await
+        {
+            Ok(emotes) => emotes,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        let emote = choose_random_emote(&emotes, game_state.chat_seed(), game_state.chat.emote);
+
+        (
+            EmoteResponse {
+                matched_chars: emote.name.as_bytes().iter().map(|_| 'ඬ').collect(),
+                url: emote.url,
+            },
+            game_state.members(),
+        )
+    };
+
+    let message =
+        Message::text(serde_json::to_string(&Response::ChatEmote(EmoteData { emote })).unwrap());
+    let mut users = app_data.users.write().await;
+    for user in members {
+        reply_to_user(&mut users, user, message.clone()).await;
+    }
+}
+
+/// Sends the room back to the lobby with everyone's scores cleared, keeping
+/// its members and settings
+pub async fn handle_restart_game(app_data: AppDataSync, user_id: User, data: RestartGameData) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&data.room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
+        }
+
+        if game_state.timer_handle.is_some() {
+            tracing::info!("Restart attempted while a game is running");
+            return;
+        }
+
+        game_state.reset_round(rand::random());
+    }
+
+    send_room_update(&app_data, &data.room_id).await;
+}
+
+pub async fn handle_transfer_ownership(
+    app_data: AppDataSync,
+    user_id: User,
+    data: TransferOwnershipData,
+) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    let new_owner = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&data.room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
+
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
+        }
+
+        let new_owner =
+            find_player_by_login(game_state, &*app_data.users.read().await, &data.login);
+        if let Some(new_owner) = &new_owner {
+            game_state.transfer_ownership(new_owner.clone());
+        }
+        new_owner
+    };
 
-        tracing::debug!("Informed {user:#?}.");
+    match new_owner {
+        Some(_) => send_room_update(&app_data, &data.room_id).await,
+        None => {
+            reply_error_to_user(
+                &app_data,
+                user_id,
+                ErrorDataType::PlayerNotInRoom,
+                format!("{} is not in this room", data.login),
+            )
+            .await
+        }
     }
+}
 
-    tracing::debug!("Done informing everyone");
+pub async fn handle_kick_player(app_data: AppDataSync, user_id: User, data: KickPlayerData) {
+    remove_player_by_owner(&app_data, user_id, &data.room_id, &data.login, false).await
 }
 
-fn choose_random_emote(emote: &Vec<FinalEmote>, seed: u64, emote_index: u32) -> FinalEmote {
-    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+pub async fn handle_ban_player(app_data: AppDataSync, user_id: User, data: BanPlayerData) {
+    remove_player_by_owner(&app_data, user_id, &data.room_id, &data.login, true).await
+}
 
-    // "wastes" the random for up to emote_index times
-    for _ in 0..emote_index {
-        let _ = emote.choose(&mut rng).unwrap();
+/// Kicks, or bans, a player on the owner's say-so
+async fn remove_player_by_owner(
+    app_data: &AppDataSync,
+    user_id: User,
+    room_id: &RoomID,
+    login: &str,
+    ban: bool,
+) {
+    if !is_user_exists(app_data, user_id.clone()).await {
+        return;
     }
 
-    emote.choose(&mut rng).unwrap().clone()
-}
+    let removed = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(room_id) {
+            Some(gs) => gs,
+            None => return,
+        };
 
-async fn send_random_emote(app_data: &mut AppDataSync, user: User, room_id: RoomID) {
-    let game_states = app_data.game_states.read().await;
-    let game_state = match game_states.get(&room_id) {
-        Some(gs) => gs,
-        None => return,
-    };
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
+        }
 
-    let game_user_data = match game_state.user_data.get(&user) {
-        Some(d) => d,
-        None => return,
+        let player = find_player_by_login(game_state, &*app_data.users.read().await, login)
+            .filter(|player| *player != user_id);
+        if let Some(player) = &player {
+            game_state.remove_player(player);
+            if ban {
+                game_state.banned.insert(player.clone());
+            }
+        }
+        player
     };
 
-    let emotes = get_emote_for_emote_set_id(EMOTE_SET_ID.to_string())
-        .await
-        .inspect_err(|e| tracing::error!("{}", e))
-        .unwrap();
-    let emote = choose_random_emote(&emotes, game_state.seed, game_user_data.emote);
+    let Some(removed) = removed else {
+        reply_error_to_user(
+            app_data,
+            user_id,
+            ErrorDataType::PlayerNotInRoom,
+            format!("{login} is not in this room"),
+        )
+        .await;
+        return;
+    };
 
-    reply_to_user(
-        &mut (*app_data.users.write().await),
-        user,
-        Message::text(
-            serde_json::to_string(&Response::Emote(EmoteData {
-                emote: EmoteResponse {
-                    matched_chars: emote.name.as_bytes().iter().map(|_| 'ඬ').collect(),
-                    url: emote.url,
-                },
-            }))
-            .unwrap(),
-        ),
-    )
-    .await;
+    let (error_type, error_msg) = if ban {
+        (ErrorDataType::Banned, "You were banned from the room")
+    } else {
+        (ErrorDataType::Kicked, "You were kicked from the room")
+    };
+    reply_error_to_user(app_data, removed, error_type, error_msg.to_string()).await;
+    send_room_update(app_data, room_id).await;
+    player_left_game(app_data, room_id.clone()).await;
 }
 
-async fn inform_room_game_state(app_data: &mut AppDataSync, room_id: RoomID) {
-    let (scores, users) = {
-        let game_states = app_data.game_states.read().await;
-        let user_data = app_data.users.read().await;
-        match game_states.get(&room_id) {
-            Some(gs) => (
-                gs.user_data
-                    .iter()
-                    .map(|(user, user_game_data)| {
-                        (
-                            user_data.get(&user).unwrap().claim.data.login.clone(),
-                            user_game_data.score.clone(),
-                        )
-                    })
-                    .collect::<HashMap<_, _>>(),
-                gs.user_data.keys().cloned().collect::<Vec<_>>(),
-            ),
-            None => return,
-        }
-    };
+/// Mutes, or unmutes, a player or chatter on the owner's say-so. Muted
+/// players still play and score, only nobody else hears from them.
+pub async fn handle_mute_player(app_data: AppDataSync, user_id: User, data: MutePlayerData) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
 
-    for user in users {
-        reply_to_user(
-            &mut (*app_data.users.write().await),
-            user,
-            Message::text(
-                serde_json::to_string(&Response::GameUpdate(GameUpdateData {
-                    scores: scores.clone(),
-                }))
-                .unwrap(),
-            ),
-        )
-        .await
+    let login = data.login.trim().to_lowercase();
+    if login.is_empty() {
+        return;
     }
-}
 
-async fn send_random_emote_to_room(app_data: &mut AppDataSync, room_id: RoomID) {
-    let users = {
-        let game_states = app_data.game_states.read().await;
-        match game_states.get(&room_id) {
-            Some(gs) => gs.user_data.keys().cloned().collect::<Vec<User>>(),
+    {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&data.room_id) {
+            Some(gs) => gs,
             None => return,
+        };
+
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
         }
-    };
 
-    for user in users {
-        send_random_emote(app_data, user, room_id.clone()).await;
+        if data.muted {
+            game_state.muted.insert(login);
+        } else {
+            game_state.muted.remove(&login);
+        }
     }
+
+    send_room_update(&app_data, &data.room_id).await
 }
 
-async fn handle_game_end(mut app_data: AppDataSync, room_id: RoomID) {
-    // inform every user in the room that the game has ended
-    let (room_owner, users) = {
+/// Moves a player to another team, on the owner's say-so
+pub async fn handle_assign_team(app_data: AppDataSync, user_id: User, data: AssignTeamData) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
+
+    let found = {
         let mut game_states = app_data.game_states.write().await;
-        let game_state = match game_states.get_mut(&room_id) {
+        let game_state = match game_states.get_mut(&data.room_id) {
             Some(gs) => gs,
             None => return,
         };
 
-        game_state.timer_handle = None;
-
-        // TODO: in theory, we should handle score calculation here as well
-        // let user_data_map = app_data.users.write().await;
-        let room_owner = game_state.room_owner.clone();
-        let users = game_state.user_data.keys().cloned().collect::<Vec<_>>();
-        (room_owner, users)
-    };
-
-    // tracing::debug!("Lock released");
-
-    // {
-    //     let mut game_states = app_data.game_states.write().await;
-    //     game_states.remove(&room_id);
-    // }
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
+        }
 
-    // tracing::debug!("Lock released again");
+        if data.team >= game_state.teams.len() {
+            tracing::info!("Assignment to team {} which doesn't exist", data.team);
+            return;
+        }
 
-    // let room_id = match create_room(&app_data, room_owner).await {
-    //     Some(r) => r,
-    //     None => {
-    //         tracing::error!("cannot create room while game over");
-    //         return;
-    //     }
-    // };
+        let player = find_player_by_login(game_state, &*app_data.users.read().await, &data.login);
+        if let Some(player) = &player {
+            game_state.team_of.insert(player.clone(), data.team);
+        }
+        player.is_some()
+    };
 
-    for user in users {
-        reply_to_user(
-            &mut (*app_data.users.write().await),
-            user.clone(),
-            Message::text(serde_json::to_string(&Response::GameOver(GameOverData {})).unwrap()),
+    if found {
+        send_room_update(&app_data, &data.room_id).await
+    } else {
+        reply_error_to_user(
+            &app_data,
+            user_id,
+            ErrorDataType::PlayerNotInRoom,
+            format!("{} is not in this room", data.login),
         )
         .await
     }
-
-    // TODO: reset all scores
 }
 
-pub async fn handle_start_game(mut app_data: AppDataSync, user_id: User, data: StartGameData) {
+pub async fn handle_set_co_host(app_data: AppDataSync, user_id: User, data: SetCoHostData) {
     if !is_user_exists(&app_data, user_id.clone()).await {
         return;
     }
 
-    let is_room_owner = {
-        let game_states = app_data.game_states.read().await;
-        let game_state = match game_states.get(&data.room_id) {
+    let found = {
+        let mut game_states = app_data.game_states.write().await;
+        let game_state = match game_states.get_mut(&data.room_id) {
             Some(gs) => gs,
             None => return,
         };
 
-        game_state.room_owner == user_id
-    };
-
-    if is_room_owner {
-        {
-            // update room seed
-            let mut game_states = app_data.game_states.write().await;
-            let game_state = match game_states.get_mut(&data.room_id) {
-                Some(gs) => gs,
-                None => return,
-            };
-            let duration = game_state.duration;
-            let cloned_appdata = app_data.clone();
-            let cloned_roomid = data.room_id.clone();
-            let seed: u64 = rand::random();
+        if !is_user_owner_of_room(game_state, user_id.clone()).await {
+            return;
+        }
 
-            game_state.timer_handle = Some(tokio::task::spawn(async move {
-                tokio::time::sleep(duration).await;
-                handle_game_end(cloned_appdata, cloned_roomid).await;
-            }));
-            game_state.seed = seed;
+        match &data.login {
+            Some(login) => {
+                let co_host =
+                    find_player_by_login(game_state, &*app_data.users.read().await, login);
+                let found = co_host.is_some();
+                if found {
+                    game_state.co_host = co_host;
+                }
+                found
+            }
+            None => {
+                game_state.co_host = None;
+                true
+            }
         }
+    };
 
-        send_random_emote_to_room(&mut app_data, data.room_id).await
+    if found {
+        send_room_update(&app_data, &data.room_id).await;
+    } else {
+        reply_error_to_user(
+            &app_data,
+            user_id,
+            ErrorDataType::PlayerNotInRoom,
+            format!("{} is not in this room", data.login.unwrap_or_default()),
+        )
+        .await
     }
 }
 
@@ -602,22 +2296,44 @@ pub async fn handle_submit_guess(mut app_data: AppDataSync, user_id: User, data:
         return;
     }
 
-    let (guessed_char, scored_increase, user_score) = {
+    let (guessed_char, scored_increase, user_score, user_combo, mode, hint) = {
         let game_states = &mut app_data.game_states.write().await;
         let game_state = match game_states.get_mut(&data.room_id) {
             Some(gs) => gs,
             None => return,
         };
 
+        let mode = game_state.mode;
+        if mode == GameMode::StreamerVsChat && game_state.room_owner != user_id {
+            tracing::info!("Only the streamer guesses in streamer vs chat");
+            return;
+        }
+        if mode == GameMode::Race && game_state.race_finished_by(&user_id) {
+            tracing::info!("Guess on a race emote the player is done with");
+            return;
+        }
+
         let user_data = match game_state.user_data.get_mut(&user_id) {
             Some(u) => u,
             None => return,
         };
 
-        let emotes = get_emote_for_emote_set_id(EMOTE_SET_ID.to_string())
+        if user_data.finished(game_state.round_limit) {
+            tracing::info!("Guess from a player who has finished the round");
+            return;
+        }
+
+        let emotes = match app_data
+            .emotes
+            .get_emotes_for_sources(&game_state.emote_sources)
             .await
-            .inspect_err(|e| tracing::error!("{}", e))
-            .unwrap();
+        {
+            Ok(emotes) => emotes,
+            Err(e) => {
+                tracing::error!("Cannot get emotes to check a guess: {e}");
+                return;
+            }
+        };
         let target_emote = choose_random_emote(&emotes, game_state.seed, user_data.emote);
 
         tracing::debug!("Target Emote: {:#?}", target_emote);
@@ -628,7 +2344,7 @@ pub async fn handle_submit_guess(mut app_data: AppDataSync, user_id: User, data:
             .chars()
             .enumerate()
             .map(|(i, target_char)| {
-                if user_emote_vec[i] == target_char {
+                if user_emote_vec.get(i) == Some(&target_char) {
                     target_char
                 } else {
                     'ඬ'
@@ -636,16 +2352,67 @@ pub async fn handle_submit_guess(mut app_data: AppDataSync, user_id: User, data:
             })
             .collect::<String>();
 
-        if target_emote.name.to_lowercase() == data.guess.to_lowercase() {
-            user_data.score += CORRECT_SCORE;
+        let scoring = game_state.scoring;
+        let correct = target_emote.name.to_lowercase() == data.guess.to_lowercase();
+        if correct && mode == GameMode::Race {
+            let combo = user_data.extend_combo();
+            let place = game_state.race.solvers.len();
+            user_data.score +=
+                scoring.points(Play::Correct { combo }) * game_state.race_rules.share(place);
+            // the player moves on with everyone else, see `progress_race`
+            game_state.race.solvers.push(user_id.clone());
+        } else if correct {
+            let combo = user_data.extend_combo();
+            user_data.score += scoring.points(Play::Correct { combo });
             user_data.emote += 1;
-            (guessed_char, true, user_data.score)
+            user_data.history.push(EmoteOutcome::Guessed);
         } else {
-            user_data.score += INCORRECT_SCORE;
-            (guessed_char, false, user_data.score)
+            if scoring.combo.break_on_wrong {
+                user_data.break_combo();
+            }
+            user_data.score += scoring.points(Play::Wrong);
+            user_data.wrong += 1;
         }
+        let (score, combo, index) = (user_data.score, user_data.combo, user_data.emote);
+        game_state.sync_chat_emote();
+
+        // only partial reveals; a right guess would hand teammates the answer
+        let revealed = guessed_char.chars().any(|c| c != 'ඬ');
+        let login = if game_state.team_hints && revealed && !correct {
+            app_data
+                .users
+                .read()
+                .await
+                .get(&user_id)
+                .map(|u| u.claim.data.login.clone())
+        } else {
+            None
+        };
+        let hint = if let Some(login) = login.filter(|l| !game_state.is_muted(l)) {
+            let hint = TeamHintData {
+                login,
+                index,
+                emote: EmoteResponse {
+                    matched_chars: guessed_char.clone(),
+                    url: target_emote.url.clone(),
+                },
+            };
+            Some((game_state.teammates_of(&user_id), hint))
+        } else {
+            None
+        };
+
+        (guessed_char, correct, score, combo, mode, hint)
     };
 
+    if let Some((teammates, hint)) = hint {
+        let message = Message::text(serde_json::to_string(&Response::TeamHint(hint)).unwrap());
+        let mut users = app_data.users.write().await;
+        for teammate in teammates {
+            reply_to_user(&mut users, teammate, message.clone()).await;
+        }
+    }
+
     reply_to_user(
         &mut (*app_data.users.write().await),
         user_id.clone(),
@@ -654,6 +2421,7 @@ pub async fn handle_submit_guess(mut app_data: AppDataSync, user_id: User, data:
                 backend::models::responses::GuessData {
                     matched_chars: guessed_char,
                     score: user_score,
+                    combo: user_combo,
                 },
             ))
             .unwrap(),
@@ -662,7 +2430,21 @@ pub async fn handle_submit_guess(mut app_data: AppDataSync, user_id: User, data:
     .await;
 
     if scored_increase {
-        send_random_emote(&mut app_data, user_id.clone(), data.room_id.clone()).await;
+        if end_game_if_done(&app_data, &data.room_id).await {
+            return;
+        }
+
+        match mode {
+            GameMode::Race => progress_race(&mut app_data, data.room_id.clone()).await,
+            _ if is_player_finished(&app_data, &data.room_id, &user_id).await => {}
+            GameMode::StreamerVsChat => {
+                send_random_emote(&mut app_data, user_id.clone(), data.room_id.clone()).await;
+                send_chat_emote_to_room(&app_data, &data.room_id).await;
+            }
+            GameMode::Classic => {
+                send_random_emote(&mut app_data, user_id.clone(), data.room_id.clone()).await
+            }
+        }
         inform_room_game_state(&mut app_data, data.room_id.clone()).await;
     }
 }
@@ -672,20 +2454,42 @@ pub async fn handle_skip(mut app_data: AppDataSync, user_id: User, data: SkipDat
         return;
     }
 
-    {
+    let mode = {
         let game_states = &mut app_data.game_states.write().await;
         let game_state = match game_states.get_mut(&data.room_id) {
             Some(gs) => gs,
             None => return,
         };
 
+        let mode = game_state.mode;
+        if mode == GameMode::StreamerVsChat && game_state.room_owner != user_id {
+            tracing::info!("Only the streamer skips in streamer vs chat");
+            return;
+        }
+        if mode == GameMode::Race && game_state.race_finished_by(&user_id) {
+            tracing::info!("Skip on a race emote the player is done with");
+            return;
+        }
+
         let user_data = match game_state.user_data.get_mut(&user_id) {
             Some(u) => u,
             None => return,
         };
 
-        user_data.score += SKIP_SCORE;
-        user_data.emote += 1;
+        if user_data.finished(game_state.round_limit) {
+            tracing::info!("Skip from a player who has finished the round");
+            return;
+        }
+
+        user_data.score += game_state.scoring.points(Play::Skip);
+        user_data.break_combo();
+        if mode == GameMode::Race {
+            // a pass; the player moves on with everyone else
+            game_state.race.passed.insert(user_id.clone());
+        } else {
+            user_data.emote += 1;
+            user_data.history.push(EmoteOutcome::Skipped);
+        }
 
         reply_to_user(
             &mut (*app_data.users.write().await),
@@ -695,70 +2499,171 @@ pub async fn handle_skip(mut app_data: AppDataSync, user_id: User, data: SkipDat
                     backend::models::responses::GuessData {
                         matched_chars: "".to_string(),
                         score: user_data.score,
+                        combo: user_data.combo,
                     },
                 ))
                 .unwrap(),
             ),
         )
         .await;
+
+        // chat can't keep guessing an emote the streamer gave up on
+        game_state.sync_chat_emote();
+        mode
+    };
+
+    if end_game_if_done(&app_data, &data.room_id).await {
+        return;
     }
 
-    send_random_emote(&mut app_data, user_id.clone(), data.room_id.clone()).await;
+    match mode {
+        GameMode::Race => progress_race(&mut app_data, data.room_id.clone()).await,
+        _ if is_player_finished(&app_data, &data.room_id, &user_id).await => {}
+        GameMode::StreamerVsChat => {
+            send_random_emote(&mut app_data, user_id.clone(), data.room_id.clone()).await;
+            send_chat_emote_to_room(&app_data, &data.room_id).await;
+        }
+        GameMode::Classic => {
+            send_random_emote(&mut app_data, user_id.clone(), data.room_id.clone()).await
+        }
+    }
+    // the skip cost points and broke the combo
+    inform_room_game_state(&mut app_data, data.room_id.clone()).await;
 }
 
+/// Attaches a new connection to the user, picking their session back up if
+/// they already had one. Returns the user and the connection's ID.
 pub async fn handle_create_user(
     app_data: AppDataSync,
     mut ws: SplitSink<WebSocket, Message>,
     claim: JWTClaim,
-) -> User {
+    read_only: bool,
+) -> (User, u64) {
+    let user = User(claim.data.id.clone());
+    let connection = app_data.next_connection_id();
     let users = &mut app_data.users.write().await;
-    let uuid = Uuid::new_v4();
-
-    let user = User(uuid.to_string());
+    let resumed = users.contains_key(&user);
 
     let _ = ws
         .send(Message::text(
             serde_json::to_string(&Response::NewUser(NewUserData {
                 user_id: user.clone(),
+                resumed,
             }))
             .unwrap(),
         ))
         .await
-        .unwrap();
+        .inspect_err(|e| tracing::warn!("Cannot send to user {}: {e}", user.0));
 
-    users.insert(
-        user.clone(),
-        backend::data::UserData {
+    let user_data = users
+        .entry(user.clone())
+        .or_insert_with(|| backend::data::UserData {
             user: user.clone(),
-            claim,
-            ws,
-        },
-    );
-
-    user
+            claim: claim.clone(),
+            connections: HashMap::new(),
+            disconnects: 0,
+        });
+    user_data.claim = claim;
+    user_data
+        .connections
+        .insert(connection, Connection { ws, read_only });
+
+    (user, connection)
 }
 
-pub async fn handle_delete_user(app_data: AppDataSync, user: User) {
-    let users = &mut app_data.users.write().await;
+/// Sends the rooms the user is in, and their current emote if a game is
+/// running, to a connection that has just attached to their session
+pub async fn handle_resume(app_data: AppDataSync, user_id: User, connection: u64) {
+    if !is_user_exists(&app_data, user_id.clone()).await {
+        return;
+    }
 
-    // TODO: find a better way to do this, ideally with a async hashmap
-    let game_states = {
-        // to release the read lock
+    let mut messages = vec![];
+    {
         let game_states = app_data.game_states.read().await;
-        game_states
-            .values()
-            .map(GameStateView::from)
-            .collect::<Vec<GameStateView>>()
-    };
+        for game_state in game_states.values() {
+            let is_player = game_state.user_data.contains_key(&user_id);
+            if !is_player && !game_state.spectators.contains_key(&user_id) {
+                continue;
+            }
 
-    for game_state in game_states {
-        if game_state.room_owner == user {
-            let mut game_states_write = app_data.game_states.write().await;
-            game_states_write.remove(&game_state.room_id);
-            break;
+            let room_join_data =
+                room_join_data(game_state, &*app_data.users.read().await, &user_id);
+            messages.push(Message::text(
+                serde_json::to_string(&Response::RoomJoin(room_join_data)).unwrap(),
+            ));
+
+            if game_state.timer_handle.is_some() {
+                messages.push(Message::text(
+                    serde_json::to_string(&Response::GameStarted).unwrap(),
+                ));
+                if let Some(emote) = current_emote(&app_data, game_state, &user_id).await {
+                    messages.push(Message::text(
+                        serde_json::to_string(&Response::Emote(EmoteData { emote })).unwrap(),
+                    ));
+                }
+            }
+        }
+    }
+
+    if messages.is_empty() {
+        messages.push(Message::text(
+            serde_json::to_string(&Response::Error(ErrorData {
+                error_type: ErrorDataType::NothingToResume,
+                error_msg: "Not in any room".to_string(),
+            }))
+            .unwrap(),
+        ));
+    }
+
+    let mut users = app_data.users.write().await;
+    for message in messages {
+        reply_to_connection(&mut users, user_id.clone(), connection, message).await;
+    }
+}
+
+/// A connection closed. Once the user's last one has, they keep their rooms
+/// and scores for a while in case they reconnect; after that they leave for
+/// good.
+pub async fn handle_delete_user(app_data: AppDataSync, user: User, connection: u64) {
+    let disconnect = {
+        let mut users = app_data.users.write().await;
+        let user_data = match users.get_mut(&user) {
+            Some(u) => u,
+            None => return,
+        };
+
+        user_data.connections.remove(&connection);
+        if !user_data.connections.is_empty() {
+            return;
         }
+
+        user_data.disconnects += 1;
+        user_data.disconnects
+    };
+
+    tracing::debug!(
+        "User {:#?} disconnected, waiting for them to come back",
+        user
+    );
+    tokio::time::sleep(Duration::from_secs(RECONNECT_GRACE_PERIOD_SEC)).await;
+
+    // a user who reconnected and dropped again since is waiting out a newer
+    // grace period, which gets to decide instead
+    let is_gone = |user_data: &UserData| {
+        user_data.connections.is_empty() && user_data.disconnects == disconnect
+    };
+
+    if !app_data.users.read().await.get(&user).is_some_and(is_gone) {
+        return;
     }
 
+    leave_all_rooms(&app_data, user.clone()).await;
+
     tracing::debug!("Removing user: {:#?}", user);
-    users.remove(&user);
+    let mut users = app_data.users.write().await;
+    if users.get(&user).is_some_and(is_gone) {
+        users.remove(&user);
+        app_data.lobby_subscribers.write().await.remove(&user);
+    }
 }
@@ -4,17 +4,31 @@ use jwt_simple::prelude::*;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::twitch::TwitchUserData;
+use crate::{data::RoomID, twitch::TwitchUserData};
+
+/// Subject of invite tokens, so they can't be passed off as user tokens or
+/// the other way around
+const INVITE_SUBJECT: &str = "room_invite";
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JWTClaim {
     pub data: TwitchUserData,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InviteClaim {
+    pub room_id: RoomID,
+    /// the room's [`crate::data::GameState::invite_nonce`]; not `nonce`,
+    /// the standard claims already have one
+    pub room_nonce: u64,
+}
+
 #[derive(Error, Debug)]
 pub enum JWTClaimError {
     #[error("JWT Error: {0}")]
     ClaimGenerationError(#[from] jwt_simple::Error),
+    #[error("Invite is for another room")]
+    WrongRoom,
 }
 
 #[derive(Debug)]
@@ -41,4 +55,134 @@ impl<K: MACLike> JWTManager<K> {
         tracing::debug!("token is {}", token);
         Ok(self.key.verify_token::<JWTClaim>(&token, None)?.custom)
     }
+
+    /// Lets whoever holds the token into the room until it expires
+    pub fn create_invite_token(
+        &self,
+        invite: InviteClaim,
+        valid_for: Duration,
+    ) -> Result<String, JWTClaimError> {
+        let claims = Claims::with_custom_claims(invite, valid_for).with_subject(INVITE_SUBJECT);
+
+        Ok(self.key.authenticate(claims)?)
+    }
+
+    /// Checks the invite is still valid and for this room, and not an
+    /// earlier one with the same code
+    pub fn verify_invite_token(
+        &self,
+        token: &str,
+        room_id: &RoomID,
+        room_nonce: u64,
+    ) -> Result<(), JWTClaimError> {
+        let options = VerificationOptions {
+            required_subject: Some(INVITE_SUBJECT.to_string()),
+            ..Default::default()
+        };
+
+        let invite = self
+            .key
+            .verify_token::<InviteClaim>(token, Some(options))?
+            .custom;
+        if invite.room_id != *room_id || invite.room_nonce != room_nonce {
+            return Err(JWTClaimError::WrongRoom);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> JWTManager<HS256Key> {
+        JWTManager::new(HS256Key::generate())
+    }
+
+    fn invite(manager: &JWTManager<HS256Key>, room_id: &str, nonce: u64) -> String {
+        let invite = InviteClaim {
+            room_id: RoomID::from(room_id.to_string()),
+            room_nonce: nonce,
+        };
+        manager
+            .create_invite_token(invite, Duration::from_mins(10))
+            .unwrap()
+    }
+
+    #[test]
+    fn accepts_invites_to_the_room() {
+        let manager = manager();
+        let token = invite(&manager, "ABCDEF", 7);
+
+        let room_id = RoomID::from("abcdef".to_string());
+        assert!(manager.verify_invite_token(&token, &room_id, 7).is_ok());
+    }
+
+    #[test]
+    fn refuses_invites_to_another_room() {
+        let manager = manager();
+        let token = invite(&manager, "ABCDEF", 7);
+
+        let other_room = RoomID::from("GHIJKL".to_string());
+        assert!(matches!(
+            manager.verify_invite_token(&token, &other_room, 7),
+            Err(JWTClaimError::WrongRoom)
+        ));
+    }
+
+    #[test]
+    fn refuses_invites_to_an_earlier_room_with_the_same_code() {
+        let manager = manager();
+        let token = invite(&manager, "ABCDEF", 7);
+
+        let room_id = RoomID::from("ABCDEF".to_string());
+        assert!(matches!(
+            manager.verify_invite_token(&token, &room_id, 8),
+            Err(JWTClaimError::WrongRoom)
+        ));
+    }
+
+    #[test]
+    fn refuses_tokens_without_the_invite_subject() {
+        let manager = manager();
+        let claims = Claims::with_custom_claims(
+            InviteClaim {
+                room_id: RoomID::from("ABCDEF".to_string()),
+                room_nonce: 7,
+            },
+            Duration::from_mins(10),
+        );
+        let token = manager.key.authenticate(claims).unwrap();
+
+        let room_id = RoomID::from("ABCDEF".to_string());
+        assert!(manager.verify_invite_token(&token, &room_id, 7).is_err());
+    }
+
+    #[test]
+    fn refuses_invites_from_another_key() {
+        let token = invite(&manager(), "ABCDEF", 7);
+
+        let room_id = RoomID::from("ABCDEF".to_string());
+        assert!(manager().verify_invite_token(&token, &room_id, 7).is_err());
+    }
+
+    #[test]
+    fn refuses_expired_invites() {
+        let manager = manager();
+        let mut claims = Claims::with_custom_claims(
+            InviteClaim {
+                room_id: RoomID::from("ABCDEF".to_string()),
+                room_nonce: 7,
+            },
+            Duration::from_mins(10),
+        )
+        .with_subject(INVITE_SUBJECT);
+        // well past the leeway jwt_simple allows for clock drift
+        claims.expires_at = Some(Clock::now_since_epoch() - Duration::from_hours(1));
+        let token = manager.key.authenticate(claims).unwrap();
+
+        let room_id = RoomID::from("ABCDEF".to_string());
+        assert!(manager.verify_invite_token(&token, &room_id, 7).is_err());
+    }
 }
@@ -2,4 +2,15 @@ pub mod models;
 pub mod data;
 pub mod seventv;
 pub mod twitch;
+pub mod twitch_chat;
 pub mod jwt;
+pub mod cache;
+pub mod emotes;
+pub mod bttv;
+pub mod ffz;
+pub mod local;
+pub mod db;
+pub mod password;
+pub mod results;
+pub mod room_code;
+pub mod scoring;
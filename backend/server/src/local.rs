@@ -0,0 +1,355 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::emotes::{EmoteError, EmoteProvider, FinalEmote};
+
+/// Route the pack's images are served from, relative to the server root
+pub const LOCAL_EMOTE_ROUTE: &str = "emotes";
+const MANIFEST_FILES: [&str; 2] = ["manifest.json", "manifest.toml"];
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "gif", "webp", "jpg", "jpeg", "avif"];
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("avif") => "image/avif",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ManifestEmote {
+    name: String,
+    /// image file, relative to the manifest
+    #[serde(default)]
+    path: Option<String>,
+    /// used as-is when the image lives somewhere else
+    #[serde(default)]
+    url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Manifest {
+    emotes: Vec<ManifestEmote>,
+}
+
+/// An emote pack on disk, for playing without network access. `path` is
+/// either a manifest (JSON or TOML) or a directory; a directory without a
+/// manifest uses every image in it, named after the file.
+#[derive(Debug, Clone)]
+pub struct LocalProvider {
+    path: PathBuf,
+    public_url: String,
+}
+
+impl LocalProvider {
+    /// `public_url` is where clients can reach this server, e.g. `http://localhost:3030`
+    pub fn new(path: PathBuf, public_url: String) -> Self {
+        LocalProvider { path, public_url }
+    }
+
+    /// Directory that image paths are relative to
+    fn root(&self) -> PathBuf {
+        if self.path.is_dir() {
+            self.path.clone()
+        } else {
+            self.path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        }
+    }
+
+    fn image_url(&self, path: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.public_url.trim_end_matches('/'),
+            LOCAL_EMOTE_ROUTE,
+            path.trim_start_matches('/')
+        )
+    }
+
+    async fn read_manifest(path: &Path) -> Result<Manifest, EmoteError> {
+        let contents = tokio::fs::read_to_string(path).await?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => {
+                toml::from_str(&contents).map_err(|e| EmoteError::ManifestError(e.to_string()))
+            }
+            _ => serde_json::from_str(&contents)
+                .map_err(|e| EmoteError::ManifestError(e.to_string())),
+        }
+    }
+
+    async fn find_manifest(&self) -> Option<PathBuf> {
+        if !self.path.is_dir() {
+            return Some(self.path.clone());
+        }
+
+        for file in MANIFEST_FILES {
+            let candidate = self.path.join(file);
+            if tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+                return Some(candidate);
+            }
+        }
+
+        None
+    }
+
+    /// Reads an image for [`LOCAL_EMOTE_ROUTE`], with its content type. Only
+    /// images the pack uses are served, never anything else in its directory,
+    /// which may well be the server's own (key, database, ...).
+    pub async fn read_image(&self, path: &str) -> Option<(Vec<u8>, &'static str)> {
+        let listed = match self.find_manifest().await {
+            Some(manifest_path) => Self::read_manifest(&manifest_path)
+                .await
+                .ok()?
+                .emotes
+                .into_iter()
+                .filter_map(|emote| emote.path)
+                .any(|listed| listed.trim_start_matches('/') == path),
+            None => self
+                .image_files()
+                .await
+                .ok()?
+                .iter()
+                .any(|file| file.file_name().and_then(|f| f.to_str()) == Some(path)),
+        };
+        if !listed {
+            return None;
+        }
+
+        let file = self.root().join(path);
+        let image = tokio::fs::read(&file).await.ok()?;
+        Some((image, content_type(&file)))
+    }
+
+    /// Images directly in the pack directory, sorted
+    async fn image_files(&self) -> Result<Vec<PathBuf>, EmoteError> {
+        let mut entries = tokio::fs::read_dir(&self.path).await?;
+        let mut files = vec![];
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_image = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+
+            if is_image {
+                files.push(path);
+            }
+        }
+
+        // keep the order stable, emotes are picked by index
+        files.sort();
+        Ok(files)
+    }
+
+    async fn scan_directory(&self) -> Result<Vec<FinalEmote>, EmoteError> {
+        Ok(self
+            .image_files()
+            .await?
+            .into_iter()
+            .flat_map(|path| {
+                let name = path.file_stem()?.to_str()?.to_string();
+                let file_name = path.file_name()?.to_str()?;
+                Some(FinalEmote {
+                    url: self.image_url(file_name),
+                    name,
+                })
+            })
+            .collect())
+    }
+}
+
+impl EmoteProvider for LocalProvider {
+    /// The pack is configured at startup, so the ID is ignored
+    async fn get_emotes(&self, _id: &str) -> Result<Vec<FinalEmote>, EmoteError> {
+        let manifest_path = match self.find_manifest().await {
+            Some(path) => path,
+            None => return self.scan_directory().await,
+        };
+
+        let manifest = Self::read_manifest(&manifest_path).await?;
+        manifest
+            .emotes
+            .into_iter()
+            .map(|emote| {
+                let url = match (emote.url, emote.path) {
+                    (Some(url), _) => url,
+                    (None, Some(path)) => self.image_url(&path),
+                    (None, None) => {
+                        return Err(EmoteError::ManifestError(format!(
+                            "emote {} needs either a path or a url",
+                            emote.name
+                        )));
+                    }
+                };
+
+                Ok(FinalEmote {
+                    name: emote.name,
+                    url,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_URL: &str = "http://localhost:3030/";
+
+    /// A fresh directory for one test's pack
+    fn pack_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("local-pack-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, file: &str, contents: &str) {
+        std::fs::write(dir.join(file), contents).unwrap();
+    }
+
+    fn names_and_urls(emotes: Vec<FinalEmote>) -> Vec<(String, String)> {
+        emotes.into_iter().map(|e| (e.name, e.url)).collect()
+    }
+
+    #[tokio::test]
+    async fn reads_a_json_manifest() {
+        let dir = pack_dir("json");
+        write(
+            &dir,
+            "manifest.json",
+            r#"{"emotes": [
+                {"name": "KEKW", "path": "kekw.png"},
+                {"name": "PogChamp", "url": "https://example.com/pog.png"}
+            ]}"#,
+        );
+
+        let provider = LocalProvider::new(dir, PUBLIC_URL.to_string());
+        let emotes = provider.get_emotes("").await.unwrap();
+
+        assert_eq!(
+            names_and_urls(emotes),
+            [
+                (
+                    "KEKW".into(),
+                    "http://localhost:3030/emotes/kekw.png".into()
+                ),
+                ("PogChamp".into(), "https://example.com/pog.png".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_a_toml_manifest() {
+        let dir = pack_dir("toml");
+        write(
+            &dir,
+            "manifest.toml",
+            r#"
+            [[emotes]]
+            name = "Sadge"
+            path = "faces/sadge.webp"
+            "#,
+        );
+
+        let provider = LocalProvider::new(dir.join("manifest.toml"), PUBLIC_URL.to_string());
+        let emotes = provider.get_emotes("").await.unwrap();
+
+        assert_eq!(
+            names_and_urls(emotes),
+            [(
+                "Sadge".into(),
+                "http://localhost:3030/emotes/faces/sadge.webp".into()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn manifest_emotes_need_a_path_or_url() {
+        let dir = pack_dir("no-image");
+        write(&dir, "manifest.json", r#"{"emotes": [{"name": "KEKW"}]}"#);
+
+        let provider = LocalProvider::new(dir, PUBLIC_URL.to_string());
+
+        assert!(provider.get_emotes("").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn scans_a_directory_without_a_manifest() {
+        let dir = pack_dir("scan");
+        write(&dir, "PogChamp.PNG", "");
+        write(&dir, "KEKW.gif", "");
+        write(&dir, "notes.txt", "");
+
+        let provider = LocalProvider::new(dir, PUBLIC_URL.to_string());
+        let emotes = provider.get_emotes("").await.unwrap();
+
+        assert_eq!(
+            names_and_urls(emotes),
+            [
+                (
+                    "KEKW".into(),
+                    "http://localhost:3030/emotes/KEKW.gif".into()
+                ),
+                (
+                    "PogChamp".into(),
+                    "http://localhost:3030/emotes/PogChamp.PNG".into()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_only_listed_images() {
+        let dir = pack_dir("serve");
+        std::fs::create_dir_all(dir.join("pack")).unwrap();
+        write(&dir, "secret.key", "key");
+        write(&dir.join("pack"), "kekw.png", "kekw");
+        write(&dir.join("pack"), "unlisted.png", "unlisted");
+        write(
+            &dir.join("pack"),
+            "manifest.json",
+            r#"{"emotes": [{"name": "KEKW", "path": "kekw.png"}]}"#,
+        );
+
+        let provider = LocalProvider::new(dir.join("pack"), PUBLIC_URL.to_string());
+
+        let (image, content_type) = provider.read_image("kekw.png").await.unwrap();
+        assert_eq!(
+            (image.as_slice(), content_type),
+            (&b"kekw"[..], "image/png")
+        );
+
+        assert!(provider.read_image("unlisted.png").await.is_none());
+        assert!(provider.read_image("manifest.json").await.is_none());
+        assert!(provider.read_image("../secret.key").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn serves_only_images_without_a_manifest() {
+        let dir = pack_dir("serve-scan");
+        std::fs::create_dir_all(dir.join("pack")).unwrap();
+        write(&dir, "secret.png", "secret");
+        write(&dir.join("pack"), "kekw.png", "kekw");
+        write(&dir.join("pack"), "emotes.db", "db");
+
+        let provider = LocalProvider::new(dir.join("pack"), PUBLIC_URL.to_string());
+
+        assert!(provider.read_image("kekw.png").await.is_some());
+        assert!(provider.read_image("emotes.db").await.is_none());
+        assert!(provider.read_image("../secret.png").await.is_none());
+    }
+}
@@ -1,21 +1,31 @@
-#![feature(map_try_insert)]
+mod api;
 mod handler;
 
 use std::{collections::HashMap, convert::Infallible, ops::Deref, str::FromStr, sync::Arc};
 
 use backend::{
+    bttv::BTTVProvider,
     data::{AppData, AppDataSync, GameState, RoomID, User},
+    db::Database,
+    emotes::EmoteProviders,
+    ffz::FFZProvider,
     jwt::{JWTClaim, JWTClaimError},
+    local::{LOCAL_EMOTE_ROUTE, LocalProvider},
     models::{
         requests::{AuthenticateData, Request},
         responses::{ErrorData, ErrorDataType, Response},
     },
-    twitch::TwitchUserResponse,
+    seventv::SevenTVProvider,
+    twitch::{TwitchProvider, TwitchUserData, TwitchUserResponse},
+    twitch_chat::DEFAULT_CHAT_SERVER,
 };
 use futures_util::{SinkExt, StreamExt, TryFutureExt};
 use handler::{
-    handle_create_room, handle_create_user, handle_delete_user, handle_edit_room, handle_join_room,
-    handle_skip, handle_start_game, handle_submit_guess,
+    handle_assign_team, handle_ban_player, handle_create_invite, handle_create_room,
+    handle_create_user, handle_delete_user, handle_edit_room, handle_join_room, handle_kick_player,
+    handle_list_rooms, handle_mute_player, handle_reload_emote_set, handle_restart_game,
+    handle_resume, handle_set_co_host, handle_skip, handle_spectate_room, handle_start_game,
+    handle_submit_guess, handle_transfer_ownership,
 };
 use jwt_simple::prelude::{HS256Key, HS512Key};
 use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
@@ -35,6 +45,19 @@ use warp::{reply::Reply, ws::WebSocket};
 // TODO: read from environment
 const CLIENT_ID: &str = "ee92s9l7bxh4fslbqh3svb3ul7hmfi";
 const KEY_FILE: &str = "secret.key";
+/// SQLite database for game history and leaderboards
+const DATABASE_PATH_VAR: &str = "DATABASE_PATH";
+const DEFAULT_DATABASE_PATH: &str = "games.db";
+const TWITCH_CHAT_SERVER_VAR: &str = "TWITCH_CHAT_SERVER";
+/// App access token for Helix, only needed for Twitch native emotes
+const TWITCH_APP_ACCESS_TOKEN_VAR: &str = "TWITCH_APP_ACCESS_TOKEN";
+/// Manifest file or directory of a local emote pack, for playing offline
+const EMOTE_PACK_VAR: &str = "EMOTE_PACK";
+/// Where clients reach this server; used to build local emote pack URLs
+const PUBLIC_URL_VAR: &str = "PUBLIC_URL";
+const DEFAULT_PUBLIC_URL: &str = "http://127.0.0.1:3030";
+/// When `1` or `true`, `/token` takes a username instead of a Twitch token (dev only!)
+const MOCK_LOGIN_VAR: &str = "MOCK_LOGIN";
 
 #[derive(Error, Debug)]
 pub enum TokenSubmissionError {
@@ -45,23 +68,59 @@ pub enum TokenSubmissionError {
     ReqwestError(#[from] reqwest::Error),
 }
 
-async fn handle_room(app_data: AppDataSync, current_user: User, request: Request) {
+async fn handle_room(app_data: AppDataSync, current_user: User, connection: u64, request: Request) {
     tracing::debug!("Incoming request {request:#?} from user {current_user:#?}");
     match request {
-        Request::CreateRoom => handle_create_room(app_data, current_user).await,
+        Request::CreateRoom(create_room_data) => {
+            handle_create_room(app_data, current_user, create_room_data).await
+        }
+        Request::ListRooms(list_rooms_data) => {
+            handle_list_rooms(app_data, current_user, list_rooms_data).await
+        }
         Request::EditRoom(edit_room_data) => {
             handle_edit_room(app_data, current_user, edit_room_data).await
         }
+        Request::ReloadEmoteSet(reload_emote_set_data) => {
+            handle_reload_emote_set(app_data, current_user, reload_emote_set_data).await
+        }
         Request::JoinRoom(join_room_data) => {
             handle_join_room(app_data, current_user, join_room_data).await
         }
+        Request::CreateInvite(create_invite_data) => {
+            handle_create_invite(app_data, current_user, create_invite_data).await
+        }
+        Request::SpectateRoom(spectate_room_data) => {
+            handle_spectate_room(app_data, current_user, spectate_room_data).await
+        }
         Request::StartGame(start_game_data) => {
             handle_start_game(app_data, current_user, start_game_data).await
         }
+        Request::RestartGame(restart_game_data) => {
+            handle_restart_game(app_data, current_user, restart_game_data).await
+        }
+        Request::TransferOwnership(transfer_ownership_data) => {
+            handle_transfer_ownership(app_data, current_user, transfer_ownership_data).await
+        }
+        Request::SetCoHost(set_co_host_data) => {
+            handle_set_co_host(app_data, current_user, set_co_host_data).await
+        }
+        Request::AssignTeam(assign_team_data) => {
+            handle_assign_team(app_data, current_user, assign_team_data).await
+        }
+        Request::KickPlayer(kick_player_data) => {
+            handle_kick_player(app_data, current_user, kick_player_data).await
+        }
+        Request::BanPlayer(ban_player_data) => {
+            handle_ban_player(app_data, current_user, ban_player_data).await
+        }
+        Request::MutePlayer(mute_player_data) => {
+            handle_mute_player(app_data, current_user, mute_player_data).await
+        }
         Request::SubmitGuess(submit_guess_data) => {
             handle_submit_guess(app_data, current_user, submit_guess_data).await
         }
         Request::Skip(skip_data) => handle_skip(app_data, current_user, skip_data).await,
+        Request::Resume => handle_resume(app_data, current_user, connection).await,
     }
 }
 
@@ -108,10 +167,28 @@ async fn handle_token_submission(
     ))
 }
 
+/// Logs in as whatever username was submitted, without asking Twitch
+fn handle_mock_token_submission(
+    app_data: AppDataSync,
+    login: String,
+) -> Result<warp::reply::Json, TokenSubmissionError> {
+    let login = login.trim().to_lowercase();
+    tracing::debug!("Mock user {} logins.", login);
+
+    Ok(warp::reply::json(&app_data.jwt.create_user_token(
+        TwitchUserData {
+            id: format!("mock-{login}"),
+            display_name: login.clone(),
+            login,
+        },
+    )?))
+}
+
+/// Returns the user's claim and whether the connection is read-only
 async fn handle_authenticate_websocket(
     app_data: &AppDataSync,
     ws: &mut WebSocket,
-) -> Option<JWTClaim> {
+) -> Option<(JWTClaim, bool)> {
     while let Some(result) = ws.next().await {
         let msg = match result {
             Ok(msg) => msg,
@@ -124,8 +201,12 @@ async fn handle_authenticate_websocket(
         tracing::debug!("msg is {:#?}", msg);
 
         match serde_json::from_str::<AuthenticateData>(msg.to_str().expect("can convert to string"))
-            .map(|data| app_data.jwt.verify_user_token(data.jwt))
-        {
+            .map(|data| {
+                app_data
+                    .jwt
+                    .verify_user_token(data.jwt)
+                    .map(|claim| (claim, data.read_only))
+            }) {
             Ok(r) => match r {
                 Ok(r) => return Some(r),
                 Err(e) => {
@@ -145,7 +226,7 @@ async fn handle_authenticate_websocket(
 }
 
 async fn handle_upgrade(app_data: AppDataSync, mut ws: WebSocket) {
-    let claim = match handle_authenticate_websocket(&app_data, &mut ws).await {
+    let (claim, read_only) = match handle_authenticate_websocket(&app_data, &mut ws).await {
         Some(c) => c,
         None => {
             tracing::warn!("Connection did not authenticate on time, return");
@@ -165,7 +246,7 @@ async fn handle_upgrade(app_data: AppDataSync, mut ws: WebSocket) {
     let (ws_tx, mut ws_rx) = ws.split();
     let (tx, mut rx) = mpsc::unbounded_channel();
 
-    let user = handle_create_user(app_data.clone(), ws_tx, claim).await;
+    let (user, connection) = handle_create_user(app_data.clone(), ws_tx, claim, read_only).await;
 
     tracing::info!("Websocket connect");
 
@@ -173,7 +254,13 @@ async fn handle_upgrade(app_data: AppDataSync, mut ws: WebSocket) {
     let user_copy = user.clone();
     tokio::task::spawn(async move {
         while let Some(request) = rx.recv().await {
-            handle_room(app_data_copy.clone(), user_copy.clone(), request).await;
+            handle_room(
+                app_data_copy.clone(),
+                user_copy.clone(),
+                connection,
+                request,
+            )
+            .await;
         }
     });
 
@@ -188,17 +275,31 @@ async fn handle_upgrade(app_data: AppDataSync, mut ws: WebSocket) {
 
         tracing::debug!("msg is {:#?}", msg);
 
-        let request = match serde_json::from_str(msg.to_str().expect("can convert to string")) {
+        if msg.is_close() {
+            break;
+        }
+
+        // pings, pongs and binary frames carry no requests
+        let text = match msg.to_str() {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+
+        let request = match serde_json::from_str(text) {
             Ok(r) => r,
             Err(e) => {
                 tracing::error!("websocket {e}");
                 continue;
             }
         };
+
+        if read_only && !matches!(request, Request::Resume) {
+            continue;
+        }
         tx.send(request).expect("can send to unbounded channel");
     }
 
-    handle_delete_user(app_data, user).await;
+    handle_delete_user(app_data, user, connection).await;
 }
 
 #[tokio::main]
@@ -214,7 +315,40 @@ async fn main() {
         HS256Key::from_bytes(&buf)
     };
 
-    let state: AppDataSync = Arc::new(AppData::new(key).into());
+    let local_emotes = std::env::var(EMOTE_PACK_VAR).ok().map(|path| {
+        LocalProvider::new(
+            path.into(),
+            std::env::var(PUBLIC_URL_VAR).unwrap_or(DEFAULT_PUBLIC_URL.to_string()),
+        )
+    });
+    let local_emote_pack = local_emotes.clone();
+    // anything else, `0` and `false` included, keeps it off
+    let mock_login = std::env::var(MOCK_LOGIN_VAR)
+        .is_ok_and(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true"));
+    if mock_login {
+        tracing::warn!("Mock login is enabled, anyone can log in as anyone");
+    }
+
+    let emotes = EmoteProviders::new(
+        SevenTVProvider::default(),
+        BTTVProvider::default(),
+        FFZProvider::default(),
+        TwitchProvider::new(
+            CLIENT_ID.to_string(),
+            std::env::var(TWITCH_APP_ACCESS_TOKEN_VAR).ok(),
+        ),
+        local_emotes,
+    );
+
+    let db = Database::open(
+        std::env::var(DATABASE_PATH_VAR).unwrap_or(DEFAULT_DATABASE_PATH.to_string()),
+    )
+    .expect("Cannot open game database");
+
+    let chat_server =
+        std::env::var(TWITCH_CHAT_SERVER_VAR).unwrap_or(DEFAULT_CHAT_SERVER.to_string());
+
+    let state: AppDataSync = Arc::new(AppData::new(key, emotes, db, chat_server).into());
     let moved_state = state.clone();
 
     let room_operations = warp::path!("ws").and(warp::ws::ws()).map(move |ws: Ws| {
@@ -223,6 +357,7 @@ async fn main() {
     });
 
     let cors = warp::cors().allow_any_origin();
+    let api_routes = api::routes(state.clone());
 
     let token_submission = warp::path!("token")
         .and(warp::post())
@@ -232,20 +367,50 @@ async fn main() {
             let state = state.clone();
             async move {
                 let state = state.clone();
+                let token = String::from_utf8(data.into_iter().collect()).unwrap();
+                let result = if mock_login {
+                    handle_mock_token_submission(state, token)
+                } else {
+                    handle_token_submission(state, token).await
+                };
+
                 Ok::<_, Infallible>(
-                    handle_token_submission(
-                        state,
-                        String::from_utf8(data.into_iter().collect()).unwrap(),
-                    )
-                    .await
-                    .map(|j| j.into_response())
-                    .inspect_err(|e| tracing::error!("Error while processing token: {e}"))
-                    .unwrap_or(warp::reply().into_response()),
+                    result
+                        .map(|j| j.into_response())
+                        .inspect_err(|e| tracing::error!("Error while processing token: {e}"))
+                        .unwrap_or(warp::reply().into_response()),
                 )
             }
         });
 
-    warp::serve(room_operations.or(token_submission).with(cors))
-        .run(([127, 0, 0, 1], 3030))
-        .await;
+    // only the images the pack lists, not the directory they're in
+    let local_emote_files = warp::path(LOCAL_EMOTE_ROUTE)
+        .and(warp::path::tail())
+        .and_then(move |tail: warp::path::Tail| {
+            let local_emote_pack = local_emote_pack.clone();
+            async move {
+                let image = match &local_emote_pack {
+                    Some(local) => local.read_image(tail.as_str()).await,
+                    None => None,
+                };
+
+                match image {
+                    Some((image, content_type)) => {
+                        let reply = warp::reply::with_header(image, "content-type", content_type);
+                        Ok(reply.into_response())
+                    }
+                    None => Err(warp::reject::not_found()),
+                }
+            }
+        });
+
+    warp::serve(
+        room_operations
+            .or(token_submission)
+            .or(api_routes)
+            .or(local_emote_files)
+            .with(cors),
+    )
+    .run(([127, 0, 0, 1], 3030))
+    .await;
 }
@@ -2,11 +2,56 @@ use std::time::Duration;
 
 use serde::Deserialize;
 
-use crate::{data::{RoomID, User}, jwt::JWTClaim};
+use crate::{
+    data::{GameMode, RoomID, RoomVisibility, User},
+    emotes::EmoteSource,
+    jwt::JWTClaim,
+    scoring::{LateJoinRules, RaceRules, RoundLimit, ScoringRules},
+};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct AuthenticateData {
-    pub jwt: String
+    pub jwt: String,
+    /// for views that only watch, like an OBS browser source; their
+    /// requests are ignored, apart from `resume`
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateRoomData {
+    /// Where to get emotes from; the default 7TV set is used when omitted
+    #[serde(default)]
+    pub emote_sources: Option<Vec<EmoteSource>>,
+    /// room code to use instead of a random one; has to be the owner's login
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub visibility: Option<RoomVisibility>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ListRoomsData {
+    /// keep sending the list whenever it changes; `false` stops it
+    #[serde(default)]
+    pub subscribe: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpectateRoomData {
+    pub room_id: RoomID,
+    /// login of the player whose emotes to show; sending this again while
+    /// spectating switches player
+    #[serde(default)]
+    pub follow: Option<String>,
+    /// seconds to hold back the followed player's emotes
+    #[serde(default)]
+    pub delay_secs: u64,
+    /// same as for joining, see [`JoinRoomData`]
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub invite: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -14,15 +59,118 @@ pub struct StartGameData {
     pub room_id: RoomID,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct RestartGameData {
+    pub room_id: RoomID,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TransferOwnershipData {
+    pub room_id: RoomID,
+    /// login of the player to hand the room to
+    pub login: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct KickPlayerData {
+    pub room_id: RoomID,
+    pub login: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BanPlayerData {
+    pub room_id: RoomID,
+    /// the player is kicked, and can't come back under another login
+    pub login: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MutePlayerData {
+    pub room_id: RoomID,
+    /// a player or, with Twitch chat playing, a chatter
+    pub login: String,
+    /// false unmutes
+    pub muted: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AssignTeamData {
+    pub room_id: RoomID,
+    pub login: String,
+    /// index into the room's teams
+    pub team: usize,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SetCoHostData {
+    pub room_id: RoomID,
+    /// login of the player to take over if the owner leaves; clears the
+    /// co-host when omitted
+    #[serde(default)]
+    pub login: Option<String>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct EditRoomData {
     pub room_id: RoomID,
     pub game_duration: u64,
+    #[serde(default)]
+    pub emote_sources: Option<Vec<EmoteSource>>,
+    /// left unchanged when omitted
+    #[serde(default)]
+    pub scoring: Option<ScoringRules>,
+    #[serde(default)]
+    pub late_join: Option<LateJoinRules>,
+    #[serde(default)]
+    pub visibility: Option<RoomVisibility>,
+    /// an empty password removes it
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Twitch logins allowed to join; an empty list lets anyone in
+    #[serde(default)]
+    pub allowlist: Option<Vec<String>>,
+    /// 0 removes the limit
+    #[serde(default)]
+    pub max_players: Option<u32>,
+    /// let the owner's Twitch chat guess along
+    #[serde(default)]
+    pub twitch_chat: Option<bool>,
+    #[serde(default)]
+    pub mode: Option<GameMode>,
+    #[serde(default)]
+    pub race: Option<RaceRules>,
+    #[serde(default)]
+    pub round_limit: Option<RoundLimit>,
+    /// team names; players are shared out between them at random. An empty
+    /// list stops playing in teams.
+    #[serde(default)]
+    pub teams: Option<Vec<String>>,
+    #[serde(default)]
+    pub team_hints: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReloadEmoteSetData {
+    pub room_id: RoomID,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct JoinRoomData {
     pub room_id: RoomID,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// token from `create_invite`; gets past the password, the allowlist and
+    /// private rooms
+    #[serde(default)]
+    pub invite: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateInviteData {
+    pub room_id: RoomID,
+    /// defaults to a day
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -39,10 +187,23 @@ pub struct SkipData {
 #[derive(Deserialize, Debug, Clone)]
 #[serde(tag = "command", rename_all = "snake_case")]
 pub enum Request {
-    CreateRoom,
+    CreateRoom(CreateRoomData),
+    ListRooms(ListRoomsData),
     EditRoom(EditRoomData),
+    ReloadEmoteSet(ReloadEmoteSetData),
     JoinRoom(JoinRoomData),
+    CreateInvite(CreateInviteData),
+    SpectateRoom(SpectateRoomData),
     StartGame(StartGameData),
+    RestartGame(RestartGameData),
+    TransferOwnership(TransferOwnershipData),
+    SetCoHost(SetCoHostData),
+    AssignTeam(AssignTeamData),
+    KickPlayer(KickPlayerData),
+    BanPlayer(BanPlayerData),
+    MutePlayer(MutePlayerData),
     SubmitGuess(SubmitGuessData),
     Skip(SkipData),
+    /// Resends the room and current emote after reconnecting
+    Resume,
 }
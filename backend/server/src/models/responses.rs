@@ -2,7 +2,11 @@ use std::collections::HashMap;
 
 use serde::Serialize;
 
-use crate::{data::{RoomID, User}, seventv::FinalEmote};
+use crate::{
+    data::{EmoteOutcome, GameMode, RoomID, RoomVisibility, User},
+    emotes::EmoteSource,
+    scoring::{LateJoinRules, RaceRules, RoundLimit, ScoringRules},
+};
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -10,6 +14,22 @@ pub enum ErrorDataType {
     AuthFailed,
     RoomJoinFailed,
     RoomDisbanded,
+    InvalidEmoteSet,
+    InvalidScoringRules,
+    PlayerNotInRoom,
+    NothingToResume,
+    InvalidRoomCode,
+    InvalidRoomSettings,
+    RoomPrivate,
+    PasswordRequired,
+    WrongPassword,
+    InvalidInvite,
+    NotOnAllowlist,
+    RoomFull,
+    Kicked,
+    Banned,
+    /// the owner's Twitch chat couldn't be reached, so chat isn't playing
+    ChatUnavailable,
 }
 
 #[derive(Serialize, Debug)]
@@ -27,6 +47,9 @@ pub struct EmoteResponse {
 #[derive(Serialize, Debug)]
 pub struct NewUserData {
     pub user_id: User,
+    /// whether an earlier connection's session was picked back up; send
+    /// `resume` to get the room back
+    pub resumed: bool,
 }
 
 // TODO: update this to say RoomUpdateData, because that's what it is
@@ -34,13 +57,47 @@ pub struct NewUserData {
 pub struct RoomJoinData {
     pub room_id: RoomID,
     pub is_owner: bool,
+    pub visibility: RoomVisibility,
+    pub has_password: bool,
+    /// logins allowed to join, if the room has an allowlist
+    pub allowlist: Option<Vec<String>>,
+    pub max_players: Option<u32>,
+    /// logins of muted players and chatters
+    pub muted: Vec<String>,
+    pub twitch_chat: bool,
+    pub mode: GameMode,
+    /// the user is watching rather than playing
+    pub spectating: bool,
+    /// login of the owner
+    pub owner: String,
+    pub co_host: Option<String>,
     pub game_duration: u64,
+    /// seconds left in the running game, if there is one
+    pub time_remaining: Option<u64>,
+    pub emote_sources: Vec<EmoteSource>,
+    pub scoring: ScoringRules,
+    pub late_join: LateJoinRules,
+    pub race: RaceRules,
+    pub round_limit: RoundLimit,
+    pub teams: Vec<TeamScore>,
+    pub team_hints: bool,
+    /// results of the room's earlier rounds, oldest first, without the
+    /// revealed emotes
+    pub past_rounds: Vec<GameOverData>,
     pub scores: HashMap<String, f32>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct GameUpdateData {
-    pub scores: HashMap<String, f32>
+    pub scores: HashMap<String, f32>,
+    /// current combo of each player, by login
+    pub combos: HashMap<String, u32>,
+    /// Twitch chat's scores, by chatter login
+    pub chat_scores: HashMap<String, f32>,
+    /// only in streamer vs chat
+    pub versus: Option<VersusData>,
+    /// empty unless the room plays in teams
+    pub teams: Vec<TeamScore>,
 }
 
 #[derive(Serialize, Debug)]
@@ -48,17 +105,141 @@ pub struct EmoteData {
     pub emote: EmoteResponse
 }
 
+/// The emote a followed player has just been given, for spectators
+#[derive(Serialize, Debug)]
+pub struct SpectatedEmoteData {
+    pub login: String,
+    pub emote: EmoteResponse,
+}
+
+/// A race emote is over; names it, now that nobody can guess it anymore
+#[derive(Serialize, Debug)]
+pub struct RaceSolvedData {
+    pub name: String,
+    pub url: String,
+    /// logins, first first; empty when everyone gave up
+    pub solvers: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TeamScore {
+    pub name: String,
+    /// sum of its players' scores
+    pub score: f32,
+    /// logins
+    pub players: Vec<String>,
+}
+
+/// What a teammate has worked out of an emote, sent when the room shares
+/// hints
+#[derive(Serialize, Debug)]
+pub struct TeamHintData {
+    pub login: String,
+    /// position of the emote in the game, the same for everyone
+    pub index: u32,
+    pub emote: EmoteResponse,
+}
+
 #[derive(Serialize, Debug)]
 pub struct GuessData {
     pub matched_chars: String,
     pub score: f32,
+    pub combo: u32,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
+pub struct RevealedEmote {
+    pub name: String,
+    pub url: String,
+    pub outcome: EmoteOutcome,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PlayerResult {
+    /// 1-based; tied players share a rank
+    pub rank: u32,
+    pub login: String,
+    pub display_name: String,
+    pub score: f32,
+    pub correct: u32,
+    pub skips: u32,
+    pub wrong: u32,
+    pub longest_combo: u32,
+    /// joined after the game had started
+    pub late: bool,
+    /// how long the player took to reach the round limit, if they did
+    pub time_taken_ms: Option<u64>,
+    /// every emote the player was shown, in order
+    pub emotes: Vec<RevealedEmote>,
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct GameOverData {
-    // TODO: winning information
-    // pub emote: FinalEmote
-    // pub new_room_id: RoomID,
+    /// best first
+    pub standings: Vec<PlayerResult>,
+    /// logins of everyone ranked first; more than one on a tie
+    pub winners: Vec<String>,
+    pub versus: Option<VersusData>,
+    /// best first; empty unless the room played in teams
+    pub teams: Vec<TeamResult>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TeamResult {
+    pub rank: u32,
+    pub name: String,
+    pub score: f32,
+    pub players: Vec<String>,
+}
+
+/// The "streamer vs chat" scoreboard: emotes each side got first
+#[derive(Serialize, Debug, Clone)]
+pub struct VersusData {
+    pub streamer: String,
+    pub streamer_points: u32,
+    pub chat_points: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomStatus {
+    Lobby,
+    InGame,
+}
+
+/// A room as listed in the room browser
+#[derive(Serialize, Debug, Clone)]
+pub struct RoomSummary {
+    pub room_id: RoomID,
+    /// login of the owner
+    pub owner: String,
+    pub owner_display_name: String,
+    pub player_count: usize,
+    pub emote_sources: Vec<EmoteSource>,
+    /// see [`crate::emotes::emote_set_key`]
+    pub emote_set: String,
+    pub game_duration: u64,
+    pub status: RoomStatus,
+    pub mode: GameMode,
+    pub has_password: bool,
+    pub max_players: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InviteData {
+    pub room_id: RoomID,
+    pub token: String,
+    pub expires_in_secs: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RoomListData {
+    pub rooms: Vec<RoomSummary>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    pub error: String,
 }
 
 #[derive(Serialize, Debug)]
@@ -66,7 +247,14 @@ pub struct GameOverData {
 pub enum Response {
     NewUser(NewUserData),
     RoomJoin(RoomJoinData),
+    RoomList(RoomListData),
+    Invite(InviteData),
     Emote(EmoteData),
+    SpectatedEmote(SpectatedEmoteData),
+    /// the emote Twitch chat is on
+    ChatEmote(EmoteData),
+    RaceSolved(RaceSolvedData),
+    TeamHint(TeamHintData),
     GuessResponse(GuessData),
     GameStarted,
     GameOver(GameOverData),
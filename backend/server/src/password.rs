@@ -0,0 +1,50 @@
+use argon2::{
+    Argon2,
+    password_hash::{
+        self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng,
+    },
+};
+
+/// Hashes a room password for storage. Slow on purpose, so call it from a
+/// blocking task.
+pub fn hash_password(password: &str) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Checks a password against a hash from [`hash_password`]. Also slow.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_the_right_password() {
+        let hash = hash_password("hunter2").unwrap();
+
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+        assert!(!verify_password("", &hash));
+    }
+
+    #[test]
+    fn salts_every_hash() {
+        assert_ne!(
+            hash_password("hunter2").unwrap(),
+            hash_password("hunter2").unwrap()
+        );
+    }
+
+    #[test]
+    fn refuses_malformed_hashes() {
+        assert!(!verify_password("hunter2", "hunter2"));
+        assert!(!verify_password("hunter2", ""));
+    }
+}
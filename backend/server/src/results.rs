@@ -0,0 +1,195 @@
+use crate::models::responses::{GameOverData, PlayerResult, TeamResult};
+
+/// Scores are ranked to a thousandth of a point; they are sums of floats, so
+/// two players with the same guesses can still differ in the last bits
+const SCORE_PRECISION: f64 = 1000.0;
+
+/// Sorts the standings best first and assigns ranks. On equal scores, whoever
+/// reached the round limit sooner goes first; players level on both share a
+/// rank and the next rank is skipped ("1, 1, 3").
+pub fn rank_standings(mut standings: Vec<PlayerResult>) -> GameOverData {
+    // players who never reached the limit come after those who did
+    let time_taken = |p: &PlayerResult| p.time_taken_ms.unwrap_or(u64::MAX);
+    standings.sort_by(|a, b| {
+        rounded(b.score)
+            .cmp(&rounded(a.score))
+            .then_with(|| time_taken(a).cmp(&time_taken(b)))
+            .then_with(|| a.login.cmp(&b.login))
+    });
+
+    let ranks = competition_ranks(&standings, |a, b| {
+        rounded(a.score) == rounded(b.score) && a.time_taken_ms == b.time_taken_ms
+    });
+    for (player, rank) in standings.iter_mut().zip(ranks) {
+        player.rank = rank;
+    }
+
+    let winners = standings
+        .iter()
+        .filter(|p| p.rank == 1)
+        .map(|p| p.login.clone())
+        .collect();
+
+    GameOverData {
+        standings,
+        winners,
+        versus: None,
+        teams: vec![],
+    }
+}
+
+/// Same as [`rank_standings`], for teams
+pub fn rank_teams(mut teams: Vec<TeamResult>) -> Vec<TeamResult> {
+    teams.sort_by(|a, b| {
+        rounded(b.score)
+            .cmp(&rounded(a.score))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    let ranks = competition_ranks(&teams, |a, b| rounded(a.score) == rounded(b.score));
+    for (team, rank) in teams.iter_mut().zip(ranks) {
+        team.rank = rank;
+    }
+
+    teams
+}
+
+/// The score as it's ranked, so sorting and ties agree
+fn rounded(score: f32) -> i64 {
+    (score as f64 * SCORE_PRECISION).round() as i64
+}
+
+/// Ranks for entries sorted best first
+fn competition_ranks<T>(sorted: &[T], same: impl Fn(&T, &T) -> bool) -> Vec<u32> {
+    let mut ranks: Vec<u32> = vec![];
+
+    for (i, entry) in sorted.iter().enumerate() {
+        let rank = if i > 0 && same(&sorted[i - 1], entry) {
+            ranks[i - 1]
+        } else {
+            i as u32 + 1
+        };
+        ranks.push(rank);
+    }
+
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(login: &str, score: f32, time_taken_ms: Option<u64>) -> PlayerResult {
+        PlayerResult {
+            rank: 0,
+            login: login.to_string(),
+            display_name: login.to_string(),
+            score,
+            correct: 0,
+            skips: 0,
+            wrong: 0,
+            longest_combo: 0,
+            late: false,
+            time_taken_ms,
+            emotes: vec![],
+        }
+    }
+
+    fn ranks(results: &GameOverData) -> Vec<(&str, u32)> {
+        results
+            .standings
+            .iter()
+            .map(|p| (p.login.as_str(), p.rank))
+            .collect()
+    }
+
+    #[test]
+    fn competition_ranks_skip_after_a_tie() {
+        assert_eq!(
+            competition_ranks(&[5, 5, 3, 2, 2, 1], |a, b| a == b),
+            [1, 1, 3, 4, 4, 6]
+        );
+        assert!(competition_ranks(&[] as &[u32], |a, b| a == b).is_empty());
+    }
+
+    #[test]
+    fn ranks_by_score() {
+        let results = rank_standings(vec![
+            player("carol", 1.0, None),
+            player("alice", 3.0, None),
+            player("bob", 3.0, None),
+            player("dave", 0.5, None),
+        ]);
+
+        assert_eq!(
+            ranks(&results),
+            [("alice", 1), ("bob", 1), ("carol", 3), ("dave", 4)]
+        );
+        assert_eq!(results.winners, ["alice", "bob"]);
+    }
+
+    #[test]
+    fn scores_within_precision_are_tied() {
+        let results = rank_standings(vec![
+            player("alice", 0.3 + 1e-6, None),
+            player("bob", 0.3, None),
+            player("carol", 0.299, None),
+        ]);
+
+        assert_eq!(ranks(&results), [("alice", 1), ("bob", 1), ("carol", 3)]);
+    }
+
+    #[test]
+    fn time_breaks_ties_within_precision() {
+        // alice is ahead by float error only, so bob's faster time counts
+        let results = rank_standings(vec![
+            player("alice", 0.3 + 1e-6, Some(9000)),
+            player("bob", 0.3, Some(4000)),
+        ]);
+
+        assert_eq!(ranks(&results), [("bob", 1), ("alice", 2)]);
+    }
+
+    #[test]
+    fn time_taken_breaks_ties() {
+        let results = rank_standings(vec![
+            player("alice", 2.0, None),
+            player("bob", 2.0, Some(9000)),
+            player("carol", 2.0, Some(4000)),
+            player("dave", 2.0, Some(4000)),
+            player("erin", 5.0, Some(20000)),
+        ]);
+
+        // a higher score still beats a faster time, and players who never
+        // reached the limit come last
+        assert_eq!(
+            ranks(&results),
+            [
+                ("erin", 1),
+                ("carol", 2),
+                ("dave", 2),
+                ("bob", 4),
+                ("alice", 5)
+            ]
+        );
+        assert_eq!(results.winners, ["erin"]);
+    }
+
+    #[test]
+    fn ranks_teams() {
+        let team = |name: &str, score| TeamResult {
+            rank: 0,
+            name: name.to_string(),
+            score,
+            players: vec![],
+        };
+        let teams = rank_teams(vec![
+            team("blue", 2.0),
+            team("red", 4.0),
+            team("green", 2.0),
+        ]);
+
+        let ranks: Vec<_> = teams.iter().map(|t| (t.name.as_str(), t.rank)).collect();
+        assert_eq!(ranks, [("red", 1), ("blue", 2), ("green", 2)]);
+    }
+}
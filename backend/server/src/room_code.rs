@@ -0,0 +1,103 @@
+use std::ops::RangeInclusive;
+
+use rand::Rng;
+
+use crate::data::RoomID;
+
+/// Leaves out characters that are easy to mix up when read off a screen or
+/// out loud on stream: 0/O, 1/I/L, 2/Z, 5/S, 8/B and U/V
+const ROOM_CODE_ALPHABET: &[u8] = b"34679ACDEFGHJKMNPQRTWXY";
+const ROOM_CODE_LENGTH: usize = 6;
+/// long enough for any Twitch login
+const VANITY_CODE_LENGTH: RangeInclusive<usize> = 3..=25;
+
+pub fn generate_room_code(rng: &mut impl Rng) -> RoomID {
+    RoomID(
+        (0..ROOM_CODE_LENGTH)
+            .map(|_| ROOM_CODE_ALPHABET[rng.random_range(0..ROOM_CODE_ALPHABET.len())] as char)
+            .collect(),
+    )
+}
+
+/// A code the room owner picked themselves. It has to be their own login, so
+/// nobody can take another streamer's code and catch their viewers.
+pub fn vanity_room_code(code: &str, owner_login: &str) -> Result<RoomID, String> {
+    let code = RoomID::from(code.to_string());
+
+    if !VANITY_CODE_LENGTH.contains(&code.0.len()) {
+        return Err(format!(
+            "Room codes must be {} to {} characters long",
+            VANITY_CODE_LENGTH.start(),
+            VANITY_CODE_LENGTH.end()
+        ));
+    }
+
+    if !code.0.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err("Room codes can only have letters, numbers and underscores".to_string());
+    }
+
+    if !code.0.eq_ignore_ascii_case(owner_login.trim()) {
+        return Err("A custom room code has to be your own Twitch login".to_string());
+    }
+
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn generated_codes_use_the_alphabet() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let code = generate_room_code(&mut rng);
+            assert_eq!(code.0.len(), ROOM_CODE_LENGTH);
+            assert!(
+                code.0.bytes().all(|c| ROOM_CODE_ALPHABET.contains(&c)),
+                "{code:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn vanity_codes_are_upper_case() {
+        assert_eq!(vanity_room_code("xQc", "xqc").unwrap().0, "XQC");
+        assert_eq!(
+            vanity_room_code("  some_streamer42 ", "some_streamer42")
+                .unwrap()
+                .0,
+            "SOME_STREAMER42"
+        );
+    }
+
+    #[test]
+    fn vanity_codes_are_the_owners_login() {
+        assert!(vanity_room_code("XQC", "xqc").is_ok());
+        assert!(vanity_room_code("xqc", "someone_else").is_err());
+        assert!(vanity_room_code("xqc", "").is_err());
+    }
+
+    #[test]
+    fn vanity_codes_have_a_length_limit() {
+        let code = |code: &str| vanity_room_code(code, code.trim());
+
+        assert!(code("ab").is_err());
+        assert!(code("abc").is_ok());
+        assert!(code(&"a".repeat(25)).is_ok());
+        assert!(code(&"a".repeat(26)).is_err());
+        // surrounding spaces don't count
+        assert!(code("  ab  ").is_err());
+    }
+
+    #[test]
+    fn vanity_codes_only_have_letters_numbers_and_underscores() {
+        for code in ["my room", "room-1", "room!", "ÄÖÜ_room", "../etc"] {
+            assert!(vanity_room_code(code, code).is_err(), "{code}");
+        }
+    }
+}
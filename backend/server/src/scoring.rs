@@ -0,0 +1,363 @@
+use serde::{Deserialize, Serialize};
+
+/// Largest number of points a single guess or skip may be worth either way
+const MAX_POINTS: f32 = 100.0;
+const MAX_MULTIPLIER: f32 = 10.0;
+
+/// How a streak of correct guesses scales the points for each one. The
+/// first guess of a combo is worth its base points, every guess after that
+/// adds `step` to the multiplier, up to `max_multiplier`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ComboRules {
+    pub step: f32,
+    pub max_multiplier: f32,
+    /// whether a wrong guess ends the combo; skipping always does
+    pub break_on_wrong: bool,
+}
+
+impl Default for ComboRules {
+    fn default() -> Self {
+        ComboRules {
+            step: 0.25,
+            max_multiplier: 3.0,
+            break_on_wrong: false,
+        }
+    }
+}
+
+impl ComboRules {
+    /// Multiplier for the guess that brought the combo to `combo`
+    pub fn multiplier(&self, combo: u32) -> f32 {
+        (1.0 + self.step * combo.saturating_sub(1) as f32).min(self.max_multiplier)
+    }
+}
+
+/// Points for everything a player can do, set per room by its owner.
+/// Penalties are negative (or zero, for a casual room).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScoringRules {
+    pub correct: f32,
+    pub wrong: f32,
+    pub skip: f32,
+    pub combo: ComboRules,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        ScoringRules {
+            correct: 1.0,
+            wrong: -0.2,
+            skip: -0.1,
+            combo: ComboRules::default(),
+        }
+    }
+}
+
+/// Something a player did that is worth points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Play {
+    /// `combo` includes this guess
+    Correct { combo: u32 },
+    Wrong,
+    Skip,
+}
+
+impl ScoringRules {
+    /// Points to add to the player's score for `play`
+    pub fn points(&self, play: Play) -> f32 {
+        match play {
+            Play::Correct { combo } => self.correct * self.combo.multiplier(combo),
+            Play::Wrong => self.wrong,
+            Play::Skip => self.skip,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [
+            self.correct,
+            self.wrong,
+            self.skip,
+            self.combo.step,
+            self.combo.max_multiplier,
+        ];
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err("Scoring values must be numbers".to_string());
+        }
+
+        if !(0.0..=MAX_POINTS).contains(&self.correct) {
+            return Err(format!("Points for a correct guess must be between 0 and {MAX_POINTS}"));
+        }
+
+        if !(-MAX_POINTS..=0.0).contains(&self.wrong) || !(-MAX_POINTS..=0.0).contains(&self.skip) {
+            return Err(format!(
+                "Penalties for wrong guesses and skips must be between -{MAX_POINTS} and 0"
+            ));
+        }
+
+        if self.combo.step < 0.0 {
+            return Err("Combo step cannot be negative".to_string());
+        }
+
+        if !(1.0..=MAX_MULTIPLIER).contains(&self.combo.max_multiplier) {
+            return Err(format!("Combo multiplier must be between 1 and {MAX_MULTIPLIER}"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether players can join a game that is already running, and what it
+/// costs them to
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct LateJoinRules {
+    pub allowed: bool,
+    /// points taken off a late player's starting score
+    pub handicap: f32,
+}
+
+impl LateJoinRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.handicap.is_finite() || !(0.0..=MAX_POINTS).contains(&self.handicap) {
+            return Err(format!("Late join handicap must be between 0 and {MAX_POINTS}"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Longest a race waits for runners-up after the first correct guess
+const MAX_RACE_WINDOW_SEC: u64 = 30;
+
+/// How a race shares out an emote. The first player to guess it gets full
+/// points, the second and third get these fractions of what they would
+/// have had, if they guess it within `window_secs` of the first.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RaceRules {
+    pub second: f32,
+    pub third: f32,
+    pub window_secs: u64,
+}
+
+impl Default for RaceRules {
+    fn default() -> Self {
+        RaceRules {
+            second: 0.0,
+            third: 0.0,
+            window_secs: 3,
+        }
+    }
+}
+
+impl RaceRules {
+    /// Fraction of the points for the player in `place`, counting from 0
+    pub fn share(&self, place: usize) -> f32 {
+        match place {
+            0 => 1.0,
+            1 => self.second,
+            2 => self.third,
+            _ => 0.0,
+        }
+    }
+
+    /// How many players can score an emote before the race moves on
+    pub fn scoring_places(&self) -> usize {
+        (0..3).take_while(|place| self.share(*place) > 0.0).count()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let shares = [self.second, self.third];
+        if shares.iter().any(|s| !s.is_finite() || !(0.0..=1.0).contains(s)) {
+            return Err("Points for second and third place must be between 0 and 1".to_string());
+        }
+
+        // third place is never reached without a second
+        if self.third > self.second {
+            return Err("Third place can't get more points than second".to_string());
+        }
+
+        if self.window_secs > MAX_RACE_WINDOW_SEC {
+            return Err(format!("Race window can be at most {MAX_RACE_WINDOW_SEC} seconds"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Most emotes a round can be limited to
+const MAX_ROUND_EMOTES: u32 = 500;
+
+/// What ends a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RoundLimit {
+    /// the room's game duration running out
+    #[default]
+    Timer,
+    /// every player having been through `count` emotes
+    Emotes { count: u32 },
+    /// somebody guessing `correct` emotes
+    FirstTo { correct: u32 },
+}
+
+impl RoundLimit {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            RoundLimit::Timer => Ok(()),
+            RoundLimit::Emotes { count: n } | RoundLimit::FirstTo { correct: n } => {
+                if (1..=MAX_ROUND_EMOTES).contains(&n) {
+                    Ok(())
+                } else {
+                    Err(format!("Rounds can be between 1 and {MAX_ROUND_EMOTES} emotes"))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combo_multiplies_correct_guesses() {
+        let rules = ScoringRules::default();
+
+        // 1 + 0.25 per guess after the first
+        assert_eq!(rules.points(Play::Correct { combo: 1 }), 1.0);
+        assert_eq!(rules.points(Play::Correct { combo: 2 }), 1.25);
+        assert_eq!(rules.points(Play::Correct { combo: 5 }), 2.0);
+        // a combo of 0 never happens, but shouldn't go below the base points
+        assert_eq!(rules.points(Play::Correct { combo: 0 }), 1.0);
+    }
+
+    #[test]
+    fn combo_stops_at_max_multiplier() {
+        let rules = ScoringRules {
+            correct: 2.0,
+            ..Default::default()
+        };
+
+        assert_eq!(rules.points(Play::Correct { combo: 9 }), 6.0);
+        assert_eq!(rules.points(Play::Correct { combo: 10 }), 6.0);
+        assert_eq!(rules.points(Play::Correct { combo: 1000 }), 6.0);
+    }
+
+    #[test]
+    fn penalties_ignore_the_combo() {
+        let rules = ScoringRules {
+            wrong: -0.5,
+            skip: -1.0,
+            ..Default::default()
+        };
+
+        assert_eq!(rules.points(Play::Wrong), -0.5);
+        assert_eq!(rules.points(Play::Skip), -1.0);
+    }
+
+    #[test]
+    fn accepts_sensible_rules() {
+        assert!(ScoringRules::default().validate().is_ok());
+
+        let casual = ScoringRules {
+            wrong: 0.0,
+            skip: 0.0,
+            combo: ComboRules {
+                step: 0.0,
+                max_multiplier: 1.0,
+                break_on_wrong: true,
+            },
+            ..Default::default()
+        };
+        assert!(casual.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_values_that_arent_numbers() {
+        let default = ScoringRules::default();
+        let invalid = [
+            ScoringRules {
+                correct: f32::NAN,
+                ..default
+            },
+            ScoringRules {
+                wrong: f32::NEG_INFINITY,
+                ..default
+            },
+            ScoringRules {
+                combo: ComboRules {
+                    step: f32::INFINITY,
+                    ..default.combo
+                },
+                ..default
+            },
+        ];
+
+        for rules in invalid {
+            assert!(rules.validate().is_err(), "{rules:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let default = ScoringRules::default();
+        let combo = |step, max_multiplier| ComboRules {
+            step,
+            max_multiplier,
+            break_on_wrong: false,
+        };
+        let invalid = [
+            ScoringRules {
+                correct: MAX_POINTS + 1.0,
+                ..default
+            },
+            ScoringRules {
+                correct: -1.0,
+                ..default
+            },
+            ScoringRules {
+                wrong: 0.5,
+                ..default
+            },
+            ScoringRules {
+                skip: 1.0,
+                ..default
+            },
+            ScoringRules {
+                skip: -MAX_POINTS - 1.0,
+                ..default
+            },
+            ScoringRules {
+                combo: combo(-0.25, 3.0),
+                ..default
+            },
+            ScoringRules {
+                combo: combo(0.25, 0.5),
+                ..default
+            },
+            ScoringRules {
+                combo: combo(0.25, MAX_MULTIPLIER + 1.0),
+                ..default
+            },
+        ];
+
+        for rules in invalid {
+            assert!(rules.validate().is_err(), "{rules:?}");
+        }
+    }
+
+    #[test]
+    fn race_places_go_down() {
+        let rules = |second, third| RaceRules {
+            second,
+            third,
+            ..Default::default()
+        };
+
+        assert!(rules(0.0, 0.0).validate().is_ok());
+        assert!(rules(0.5, 0.0).validate().is_ok());
+        assert!(rules(0.5, 0.5).validate().is_ok());
+        assert!(rules(0.0, 0.25).validate().is_err());
+        assert!(rules(0.25, 0.5).validate().is_err());
+        assert_eq!(rules(0.5, 0.25).scoring_places(), 3);
+    }
+}
@@ -1,35 +1,17 @@
 use graphql_client::{GraphQLQuery, Response};
 use reqwest;
-use serde::Serialize;
-use thiserror::Error;
-use cached::proc_macro::once;
+
+use crate::emotes::{EmoteError, EmoteProvider, FinalEmote, provider_client};
 
 #[derive(GraphQLQuery)]
 #[graphql(schema_path = "schema.json", query_path = "emotes.graphql")]
 struct Emotes;
 
-#[derive(Debug, Serialize, Clone)]
-pub struct FinalEmote {
-    pub name: String,
-    pub url: String,
-}
-
-#[derive(Debug, Error)]
-pub enum EmoteError {
-    #[error("Response body extraction error")]
-    ResponseError,
-
-    #[error("Reqwest error when decoding response {0}")]
-    ReqwestError(#[from] reqwest::Error),
-
-    #[error("Unknown error")]
-    Unknown,
-}
-
 const GRAPHQL_HOST: &str = "https://7tv.io/v3/gql";
 
 fn get_emote_url(emote_data: emotes::EmotesEmoteSetEmotesDataHost) -> String {
-    let host = emote_data.url;
+    // 7TV hands out protocol-relative hosts
+    let host = emote_data.url.trim_start_matches('/').to_string();
     let files = emote_data
         .files
         .into_iter()
@@ -39,41 +21,125 @@ fn get_emote_url(emote_data: emotes::EmotesEmoteSetEmotesDataHost) -> String {
 
     for file in &files {
         if file.contains(".gif") {
-            return format!("{}/{}", host, file);
+            return format!("https://{}/{}", host, file);
         }
     }
 
     return format!("https://{}/{}", host, files[0]);
 }
 
-async fn get_emotes_for_emote_set(
-    variables: emotes::Variables,
-) -> Result<Vec<FinalEmote>, EmoteError> {
-    let request_body = Emotes::build_query(variables);
+#[derive(Debug, Clone)]
+pub struct SevenTVProvider {
+    client: reqwest::Client,
+    graphql_host: String,
+}
 
-    let client = reqwest::Client::new();
-    let res = client.post(GRAPHQL_HOST).json(&request_body).send().await?;
-    let response_body: Response<emotes::ResponseData> = res.json().await?;
+impl Default for SevenTVProvider {
+    fn default() -> Self {
+        Self::new(GRAPHQL_HOST.to_string())
+    }
+}
 
-    let data = response_body.data.ok_or(EmoteError::ResponseError)?;
-    Ok(data
-        .emote_set
-        .emotes
-        .into_iter()
-        .map(|item| FinalEmote {
-            name: item.name,
-            url: get_emote_url(item.data.host),
+impl SevenTVProvider {
+    pub fn new(graphql_host: String) -> Self {
+        SevenTVProvider {
+            client: provider_client(),
+            graphql_host,
+        }
+    }
+
+    async fn get_emotes_for_emote_set(
+        &self,
+        variables: emotes::Variables,
+    ) -> Result<Vec<FinalEmote>, EmoteError> {
+        let request_body = Emotes::build_query(variables);
+
+        let res = self
+            .client
+            .post(&self.graphql_host)
+            .json(&request_body)
+            .send()
+            .await?;
+        let response_body: Response<emotes::ResponseData> = res.json().await?;
+
+        let data = response_body.data.ok_or(EmoteError::ResponseError)?;
+        Ok(data
+            .emote_set
+            .emotes
+            .into_iter()
+            .map(|item| FinalEmote {
+                name: item.name,
+                url: get_emote_url(item.data.host),
+            })
+            .collect())
+    }
+}
+
+impl EmoteProvider for SevenTVProvider {
+    async fn get_emotes(&self, emote_set_id: &str) -> Result<Vec<FinalEmote>, EmoteError> {
+        self.get_emotes_for_emote_set(emotes::Variables {
+            id: emote_set_id.to_string(),
         })
-        .collect())
+        .await
+    }
 }
 
-#[once(time=10800, result=true)]
-pub async fn get_emote_for_emote_set_id(
-    emote_set_id: String,
-) -> Result<Vec<FinalEmote>, EmoteError> {
-    let variable = emotes::Variables {
-        id: emote_set_id,
-    };
+#[cfg(test)]
+mod tests {
+    use warp::Filter;
+
+    use super::*;
 
-    get_emotes_for_emote_set(variable).await
+    async fn mock_provider() -> SevenTVProvider {
+        let gql = warp::path!("v3" / "gql")
+            .and(warp::post())
+            .and(warp::body::json())
+            .map(|body: serde_json::Value| {
+                let fixture = match body["variables"]["id"].as_str() {
+                    Some("01GN2QZDS0000BKRM8E4JJD3NV") => {
+                        include_str!("../fixtures/seventv_emote_set.json")
+                    }
+                    _ => include_str!("../fixtures/seventv_not_found.json"),
+                };
+                warp::reply::with_header(fixture, "content-type", "application/json")
+            });
+        let (addr, server) = warp::serve(gql).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        SevenTVProvider::new(format!("http://{addr}/v3/gql"))
+    }
+
+    #[tokio::test]
+    async fn parses_emote_set() {
+        let emotes = mock_provider()
+            .await
+            .get_emotes("01GN2QZDS0000BKRM8E4JJD3NV")
+            .await
+            .unwrap();
+
+        let emotes: Vec<_> = emotes
+            .iter()
+            .map(|e| (e.name.as_str(), e.url.as_str()))
+            .collect();
+        assert_eq!(
+            emotes,
+            [
+                (
+                    "peepoHappy",
+                    "https://cdn.7tv.app/emote/60ae2e3db2ecb01505c6f69d/4x.webp"
+                ),
+                // animated emotes use the gif
+                (
+                    "catKISS",
+                    "https://cdn.7tv.app/emote/60afcde452a13d1adba73d29/4x.gif"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_emote_set_is_an_error() {
+        let result = mock_provider().await.get_emotes("does-not-exist").await;
+        assert!(matches!(result, Err(EmoteError::ResponseError)));
+    }
 }
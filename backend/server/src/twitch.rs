@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
 
+use crate::emotes::{EmoteError, EmoteProvider, FinalEmote, provider_client};
+
+const HELIX_HOST: &str = "https://api.twitch.tv/helix";
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TwitchUserData {
     pub id: String,
@@ -11,3 +15,173 @@ pub struct TwitchUserData {
 pub struct TwitchUserResponse {
     pub data: Vec<TwitchUserData>
 }
+
+#[derive(Deserialize, Debug)]
+struct TwitchEmote {
+    id: String,
+    name: String,
+    format: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TwitchEmoteResponse {
+    data: Vec<TwitchEmote>,
+    /// e.g. `https://static-cdn.jtvnw.net/emoticons/v2/{{id}}/{{format}}/{{theme_mode}}/{{scale}}`
+    template: String,
+}
+
+/// Twitch native emotes through Helix. Helix needs an app access token, so
+/// the provider refuses to fetch anything without one.
+#[derive(Debug, Clone)]
+pub struct TwitchProvider {
+    client: reqwest::Client,
+    helix_host: String,
+    client_id: String,
+    access_token: Option<String>,
+}
+
+impl TwitchProvider {
+    pub fn new(client_id: String, access_token: Option<String>) -> Self {
+        Self::with_host(HELIX_HOST.to_string(), client_id, access_token)
+    }
+
+    pub fn with_host(helix_host: String, client_id: String, access_token: Option<String>) -> Self {
+        TwitchProvider {
+            client: provider_client(),
+            helix_host,
+            client_id,
+            access_token,
+        }
+    }
+
+    async fn fetch_emotes(&self, path: &str) -> Result<Vec<FinalEmote>, EmoteError> {
+        let access_token = self
+            .access_token
+            .as_ref()
+            .ok_or(EmoteError::NotConfigured("Twitch app access token"))?;
+
+        let response: TwitchEmoteResponse = self
+            .client
+            .get(format!("{}{}", self.helix_host, path))
+            .bearer_auth(access_token)
+            .header("Client-Id", &self.client_id)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response
+            .data
+            .into_iter()
+            .map(|emote| {
+                let format = if emote.format.iter().any(|f| f == "animated") {
+                    "animated"
+                } else {
+                    "static"
+                };
+
+                FinalEmote {
+                    url: response
+                        .template
+                        .replace("{{id}}", &emote.id)
+                        .replace("{{format}}", format)
+                        .replace("{{theme_mode}}", "dark")
+                        .replace("{{scale}}", "3.0"),
+                    name: emote.name,
+                }
+            })
+            .collect())
+    }
+
+    pub async fn get_global_emotes(&self) -> Result<Vec<FinalEmote>, EmoteError> {
+        self.fetch_emotes("/chat/emotes/global").await
+    }
+}
+
+impl EmoteProvider for TwitchProvider {
+    async fn get_emotes(&self, broadcaster_id: &str) -> Result<Vec<FinalEmote>, EmoteError> {
+        self.fetch_emotes(&format!("/chat/emotes?broadcaster_id={broadcaster_id}"))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use warp::{Filter, http::StatusCode};
+
+    use super::*;
+
+    async fn mock_provider(access_token: Option<&str>) -> TwitchProvider {
+        let emotes = warp::path!("chat" / "emotes")
+            .and(warp::header::exact("authorization", "Bearer app-token"))
+            .and(warp::header::exact("client-id", "client-id"))
+            .and(warp::query::<HashMap<String, String>>())
+            .map(|query: HashMap<String, String>| {
+                let (body, status) = match query.get("broadcaster_id").map(String::as_str) {
+                    Some("141981764") => (
+                        include_str!("../fixtures/twitch_channel_emotes.json"),
+                        StatusCode::OK,
+                    ),
+                    _ => (
+                        r#"{"error":"Not Found","status":404,"message":"broadcaster not found"}"#,
+                        StatusCode::NOT_FOUND,
+                    ),
+                };
+                warp::reply::with_status(
+                    warp::reply::with_header(body, "content-type", "application/json"),
+                    status,
+                )
+            });
+        let (addr, server) = warp::serve(emotes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        TwitchProvider::with_host(
+            format!("http://{addr}"),
+            "client-id".to_string(),
+            access_token.map(str::to_string),
+        )
+    }
+
+    #[tokio::test]
+    async fn parses_channel_emotes() {
+        let provider = mock_provider(Some("app-token")).await;
+        let emotes = provider.get_emotes("141981764").await.unwrap();
+
+        let emotes: Vec<_> = emotes
+            .iter()
+            .map(|e| (e.name.as_str(), e.url.as_str()))
+            .collect();
+        assert_eq!(
+            emotes,
+            [
+                (
+                    "twitchdevPitchfork",
+                    "https://static-cdn.jtvnw.net/emoticons/v2/304456832/static/dark/3.0"
+                ),
+                (
+                    "twitchdevHype",
+                    "https://static-cdn.jtvnw.net/emoticons/v2/\
+                     emotesv2_dc24652ada1e4c84a5e3ceebae4de709/animated/dark/3.0"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_broadcaster_is_an_error() {
+        let result = mock_provider(Some("app-token")).await.get_emotes("1").await;
+        assert!(matches!(
+            result,
+            Err(EmoteError::ReqwestError(e)) if e.status().is_some_and(|s| s.as_u16() == 404)
+        ));
+    }
+
+    #[tokio::test]
+    async fn needs_an_access_token() {
+        let result = mock_provider(None).await.get_emotes("141981764").await;
+        assert!(matches!(result, Err(EmoteError::NotConfigured(_))));
+    }
+}
@@ -0,0 +1,181 @@
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+};
+
+/// Twitch chat over plain IRC
+pub const DEFAULT_CHAT_SERVER: &str = "irc.chat.twitch.tv:6667";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// lowercase Twitch login of the chatter
+    pub login: String,
+    pub text: String,
+}
+
+#[derive(Error, Debug)]
+pub enum TwitchChatError {
+    #[error("IO Error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Chat server closed the connection")]
+    Closed,
+}
+
+/// Read-only connection to one channel's chat. Logs in anonymously, so it
+/// needs no token but can't send anything.
+#[derive(Debug)]
+pub struct ChatReader {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl ChatReader {
+    pub async fn connect(server: &str, channel: &str) -> Result<Self, TwitchChatError> {
+        let (reader, mut writer) = TcpStream::connect(server).await?.into_split();
+
+        let nick = format!("justinfan{}", rand::random_range(10000..100000));
+        let login = format!("NICK {nick}\r\nJOIN #{}\r\n", channel.to_lowercase());
+        writer.write_all(login.as_bytes()).await?;
+
+        Ok(ChatReader {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        })
+    }
+
+    /// Waits for the next chat message, answering pings on the way
+    pub async fn next_message(&mut self) -> Result<ChatMessage, TwitchChatError> {
+        loop {
+            let line = self
+                .lines
+                .next_line()
+                .await?
+                .ok_or(TwitchChatError::Closed)?;
+
+            if let Some(payload) = line.strip_prefix("PING") {
+                self.writer
+                    .write_all(format!("PONG{payload}\r\n").as_bytes())
+                    .await?;
+                continue;
+            }
+
+            if let Some(message) = parse_privmsg(&line) {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+/// Picks the chatter and text out of a line like
+/// `:login!login@login.tmi.twitch.tv PRIVMSG #channel :text`, tags or not
+pub fn parse_privmsg(line: &str) -> Option<ChatMessage> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let line = match line.strip_prefix('@') {
+        Some(tagged) => tagged.split_once(' ')?.1,
+        None => line,
+    };
+
+    let (prefix, rest) = line.strip_prefix(':')?.split_once(' ')?;
+    let (command, rest) = rest.split_once(' ')?;
+    if command != "PRIVMSG" {
+        return None;
+    }
+
+    let (_channel, text) = rest.split_once(" :")?;
+    let login = prefix.split('!').next()?;
+    if login.is_empty() {
+        return None;
+    }
+
+    Some(ChatMessage {
+        login: login.to_lowercase(),
+        text: text.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    fn message(login: &str, text: &str) -> Option<ChatMessage> {
+        Some(ChatMessage {
+            login: login.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    #[test]
+    fn parses_untagged_privmsg() {
+        let line = ":Someone!someone@someone.tmi.twitch.tv PRIVMSG #channel :OMEGALUL\r\n";
+        assert_eq!(parse_privmsg(line), message("someone", "OMEGALUL"));
+    }
+
+    #[test]
+    fn parses_tagged_privmsg() {
+        let line = "@badge-info=;color=#FF0000;display-name=Someone \
+            :someone!someone@someone.tmi.twitch.tv PRIVMSG #channel :Kappa :)";
+        assert_eq!(parse_privmsg(line), message("someone", "Kappa :)"));
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        assert_eq!(parse_privmsg("PING :tmi.twitch.tv"), None);
+        assert_eq!(
+            parse_privmsg(":someone!someone@someone.tmi.twitch.tv JOIN #channel"),
+            None
+        );
+        assert_eq!(
+            parse_privmsg(":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!"),
+            None
+        );
+        assert_eq!(parse_privmsg(""), None);
+    }
+
+    #[tokio::test]
+    async fn reads_messages_and_answers_pings() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            let nick = lines.next_line().await.unwrap().unwrap();
+            assert!(nick.starts_with("NICK justinfan"), "{nick}");
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "JOIN #channel");
+
+            writer
+                .write_all(
+                    b":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!\r\n\
+                    :a!a@a.tmi.twitch.tv PRIVMSG #channel :first\r\n\
+                    PING :tmi.twitch.tv\r\n\
+                    @color=#00FF00 :B!b@b.tmi.twitch.tv PRIVMSG #channel :second\r\n",
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(
+                lines.next_line().await.unwrap().unwrap(),
+                "PONG :tmi.twitch.tv"
+            );
+        });
+
+        let mut reader = ChatReader::connect(&addr, "Channel").await.unwrap();
+        assert_eq!(reader.next_message().await.ok(), message("a", "first"));
+        assert_eq!(reader.next_message().await.ok(), message("b", "second"));
+
+        server.await.unwrap();
+        assert!(matches!(
+            reader.next_message().await,
+            Err(TwitchChatError::Closed)
+        ));
+    }
+}
@@ -1,22 +1,113 @@
 /** Requests */
 
+export type EmoteSource =
+  | { provider: '7tv'; id: string }
+  | { provider: 'bttv'; id: string }
+  | { provider: 'ffz'; id: string }
+  | { provider: 'twitch_channel'; id: string }
+  | { provider: 'twitch_global' }
+  | { provider: 'local' };
+
+export type ScoringRules = {
+  correct: number;
+  wrong: number;
+  skip: number;
+  combo: {
+    step: number;
+    max_multiplier: number;
+    break_on_wrong: boolean;
+  };
+};
+
+export type RoomVisibility = 'public' | 'unlisted' | 'private';
+
+export type GameMode = 'classic' | 'streamer_vs_chat' | 'race';
+
+/** second and third are fractions of the winner's points */
+export type RaceRules = {
+  second: number;
+  third: number;
+  window_secs: number;
+};
+
+/** how a round ends, a timer is the default; other limits still end the
+ * game after ten times its duration */
+export type RoundLimit =
+  | { kind: 'timer' }
+  | { kind: 'emotes'; count: number }
+  | { kind: 'first_to'; correct: number };
+
+export type LateJoinRules = {
+  allowed: boolean;
+  handicap: number;
+};
+
 export type Authenticate = {
   jwt: string;
+  read_only?: boolean;
 };
 
 export type CreateRoomRequest = {
   command: 'create_room';
+  emote_sources?: EmoteSource[];
+  /** instead of a random code; has to be the owner's own login */
+  code?: string;
+  visibility?: RoomVisibility;
+};
+
+export type ListRoomsRequest = {
+  command: 'list_rooms';
+  subscribe?: boolean;
 };
 
 export type EditRoomRequest = {
   command: 'edit_room';
   room_id: string;
   game_duration: number;
+  emote_sources?: EmoteSource[];
+  scoring?: ScoringRules;
+  late_join?: LateJoinRules;
+  visibility?: RoomVisibility;
+  /** empty string removes the password */
+  password?: string;
+  /** empty list removes the allowlist */
+  allowlist?: string[];
+  /** 0 removes the limit */
+  max_players?: number;
+  twitch_chat?: boolean;
+  mode?: GameMode;
+  race?: RaceRules;
+  round_limit?: RoundLimit;
+  /** empty list stops playing in teams */
+  teams?: string[];
+  team_hints?: boolean;
+};
+
+export type ReloadEmoteSetRequest = {
+  command: 'reload_emote_set';
+  room_id: string;
 };
 
 export type JoinRoomRequest = {
   command: 'join_room';
   room_id: string;
+  password?: string;
+  invite?: string;
+};
+
+export type CreateInviteRequest = {
+  command: 'create_invite';
+  room_id: string;
+  expires_in_secs?: number;
+};
+
+export type SpectateRoomRequest = {
+  command: 'spectate_room';
+  room_id: string;
+  follow?: string;
+  delay_secs?: number;
+  password?: string;
+  invite?: string;
 };
 
 export type StartGameRequest = {
@@ -24,6 +115,52 @@ export type StartGameRequest = {
   room_id: string;
 };
 
+export type RestartGameRequest = {
+  command: 'restart_game';
+  room_id: string;
+};
+
+export type TransferOwnershipRequest = {
+  command: 'transfer_ownership';
+  room_id: string;
+  login: string;
+};
+
+export type SetCoHostRequest = {
+  command: 'set_co_host';
+  room_id: string;
+  login?: string;
+};
+
+export type AssignTeamRequest = {
+  command: 'assign_team';
+  room_id: string;
+  login: string;
+  /** index into the room's teams */
+  team: number;
+};
+
+export type KickPlayerRequest = {
+  command: 'kick_player';
+  room_id: string;
+  login: string;
+};
+
+export type BanPlayerRequest = {
+  command: 'ban_player';
+  room_id: string;
+  login: string;
+};
+
+/** a muted player or chatter still plays, but sends no team hints and
+ * chat ignores them */
+export type MutePlayerRequest = {
+  command: 'mute_player';
+  room_id: string;
+  login: string;
+  muted: boolean;
+};
+
 export type SubmitGuessRequest = {
   command: 'submit_guess';
   room_id: string;
@@ -35,13 +172,29 @@ export type SkipRequest = {
   room_id: string;
 };
 
+export type ResumeRequest = {
+  command: 'resume';
+};
+
 export type Request =
   | CreateRoomRequest
+  | ListRoomsRequest
   | EditRoomRequest
+  | ReloadEmoteSetRequest
   | JoinRoomRequest
+  | CreateInviteRequest
+  | SpectateRoomRequest
   | StartGameRequest
+  | RestartGameRequest
+  | TransferOwnershipRequest
+  | SetCoHostRequest
+  | AssignTeamRequest
+  | KickPlayerRequest
+  | BanPlayerRequest
+  | MutePlayerRequest
   | SubmitGuessRequest
   | SkipRequest
+  | ResumeRequest
   | Authenticate;
 
 /** Responses */
@@ -54,16 +207,69 @@ export type ErrorResponse = {
 export type NewUserResponse = {
   command: 'new_user';
   user_id: string;
+  resumed: boolean;
 };
 
 export type RoomJoinResponse = {
   command: 'room_join';
   room_id: string;
   is_owner: boolean;
+  visibility: RoomVisibility;
+  has_password: boolean;
+  allowlist: string[] | null;
+  max_players: number | null;
+  muted: string[];
+  twitch_chat: boolean;
+  mode: GameMode;
+  spectating: boolean;
+  owner: string;
+  co_host: string | null;
   game_duration: number;
+  time_remaining: number | null;
+  emote_sources: EmoteSource[];
+  scoring: ScoringRules;
+  late_join: LateJoinRules;
+  race: RaceRules;
+  round_limit: RoundLimit;
+  teams: TeamScore[];
+  team_hints: boolean;
+  /** oldest first; the standings leave out the revealed emotes */
+  past_rounds: Omit<GameOverResponse, 'command'>[];
   scores: { [uid: string]: number }[];
 };
 
+export type TeamScore = {
+  name: string;
+  score: number;
+  players: string[];
+};
+
+export type RoomSummary = {
+  room_id: string;
+  owner: string;
+  owner_display_name: string;
+  player_count: number;
+  emote_sources: EmoteSource[];
+  emote_set: string;
+  game_duration: number;
+  status: 'lobby' | 'in_game';
+  has_password: boolean;
+  max_players: number | null;
+  mode: GameMode;
+};
+
+export type RoomListResponse = {
+  command: 'room_list';
+  rooms: RoomSummary[];
+};
+
+export type InviteResponse = {
+  command: 'invite';
+  room_id: string;
+  token: string;
+  expires_in_secs: number;
+};
+
 export type EmoteDataResponse = {
   command: 'emote';
   emote: {
@@ -72,34 +278,107 @@ export type EmoteDataResponse = {
   };
 };
 
+export type SpectatedEmoteResponse = {
+  command: 'spectated_emote';
+  login: string;
+  emote: {
+    matched_chars: string;
+    url: string;
+  };
+};
+
+export type ChatEmoteResponse = {
+  command: 'chat_emote';
+  emote: {
+    matched_chars: string;
+    url: string;
+  };
+};
+
+export type RaceSolvedResponse = {
+  command: 'race_solved';
+  name: string;
+  url: string;
+  solvers: string[];
+};
+
+export type TeamHintResponse = {
+  command: 'team_hint';
+  login: string;
+  index: number;
+  emote: {
+    matched_chars: string;
+    url: string;
+  };
+};
+
 export type GuessDataResponse = {
   command: 'guess_response';
   matched_chars: string;
   score: number;
+  combo: number;
 };
 
 export type GameStartedResponse = {
   command: 'game_started';
 };
 
+export type EmoteOutcome = 'guessed' | 'skipped' | 'unfinished' | 'taken_by_chat' | 'missed';
+
+export type RevealedEmote = {
+  name: string;
+  url: string;
+  outcome: EmoteOutcome;
+};
+
+export type PlayerResult = {
+  rank: number;
+  login: string;
+  display_name: string;
+  score: number;
+  correct: number;
+  skips: number;
+  wrong: number;
+  longest_combo: number;
+  late: boolean;
+  /** when the player reached the round limit, null with a timer */
+  time_taken_ms: number | null;
+  emotes: RevealedEmote[];
+};
+
+export type VersusScore = {
+  streamer: string;
+  streamer_points: number;
+  chat_points: number;
+};
+
 export type GameOverResponse = {
   command: 'game_over';
-  // new_room_id: string;
-  // emote: {
-  //   name: string,
-  //   id: string,
-  // }
+  standings: PlayerResult[];
+  winners: string[];
+  versus: VersusScore | null;
+  teams: (TeamScore & { rank: number })[];
 };
 
 export type GameUpdateResponse = {
   command: 'game_update';
   scores: { [uid: string]: number }[];
+  combos: { [login: string]: number };
+  chat_scores: { [login: string]: number };
+  versus: VersusScore | null;
+  teams: TeamScore[];
 };
 
 export type ResponsesCommands =
   | 'new_user'
   | 'room_join'
+  | 'room_list'
+  | 'invite'
   | 'emote'
+  | 'spectated_emote'
+  | 'chat_emote'
+  | 'race_solved'
+  | 'team_hint'
   | 'guess_response'
   | 'game_started'
   | 'game_over'
@@ -108,11 +387,35 @@ export type ResponsesCommands =
 export type Response =
   | NewUserResponse
   | RoomJoinResponse
+  | RoomListResponse
+  | InviteResponse
   | EmoteDataResponse
+  | SpectatedEmoteResponse
+  | ChatEmoteResponse
+  | RaceSolvedResponse
+  | TeamHintResponse
   | GuessDataResponse
   | GameStartedResponse
   | GameOverResponse
   | GameUpdateResponse
   | ErrorResponse;
 
-export type ErrorTypes = 'auth_failed' | 'room_join_failed' | 'room_disbanded';
+export type ErrorTypes =
+  | 'auth_failed'
+  | 'room_join_failed'
+  | 'room_disbanded'
+  | 'invalid_emote_set'
+  | 'invalid_scoring_rules'
+  | 'player_not_in_room'
+  | 'nothing_to_resume'
+  | 'invalid_room_code'
+  | 'invalid_room_settings'
+  | 'room_private'
+  | 'password_required'
+  | 'wrong_password'
+  | 'invalid_invite'
+  | 'not_on_allowlist'
+  | 'room_full'
+  | 'kicked'
+  | 'banned'
+  | 'chat_unavailable';